@@ -27,6 +27,129 @@ Mega.arguments = {}
 --- Speech-to-text is hard, isn't it?
 Mega.raw_arguments = {}
 
---- Speak a string.
+--- All the numbers Mega found in the arguments, in order.  
+--- Spoken numbers get put back together, so "roll twenty three dee six" gives `{23, 6}`.
+--- All the guesses in `raw_arguments` are checked, so this might find a "four" that `arguments` heard as "for".
+Mega.numbers = {}
+
+--- The arguments, filled into the slots this command asked for in `_mega.toml`.  
+--- With `slots = ["count: number?", "dee", "sides: number"]`, saying "roll two dee twenty"
+--- gives `{count = 2, sides = 20}`. Slots that weren't said are nil, but Mega asks for
+--- the ones without a `?` before running the command, so those are always there.
+--- `number` slots are numbers; `word`, `words` and `spelled` slots are strings.
+--- `spelled` slots are read letter by letter, like "capital k nine alpha" for `"K9a"`.
+Mega.slots = {}
+
+--- How sure Mega is that it heard this command right, from 0 to 1.  
+--- This is roughly how much of the speech-to-text's guesses agree on the command.
+--- Commands that do something drastic might want to double-check if this is low.
+Mega.confidence = 1
+
+--- Somewhere to keep things between runs of this command, like a shopping list or a high score.  
+--- Every command has its own, saved in the `store` folder (see `mega.toml`). Changes are saved straight away.
+--- You can store strings, numbers, booleans, and tables of those.
+--- There's a size limit, so it's not for anything huge.
+Mega.store = {}
+
+--- Get something back out of the store.
+--- @param key string What it was stored as
+--- @return any value What was stored, or nil if nothing was
+function Mega.store.get(key) end
+
+--- Put something in the store. Storing nil deletes it.  
+--- This errors if the store would get too big, or the value can't be stored (like a function).
+--- @param key string What to store it as
+--- @param value any What to store
+function Mega.store.set(key, value) end
+
+--- Take something out of the store.
+--- @param key string What it was stored as
+--- @return boolean deleted Whether there was anything there
+function Mega.store.delete(key) end
+
+--- Everything that's in the store.
+--- @return table keys The keys, in alphabetical order
+function Mega.store.keys() end
+
+--- Speak a string.  
+--- Things are said one after another, in order, and this returns straight away without waiting.
+--- Pass `{wait = true}` to wait until it's been said (see `speak_and_wait`), or a function
+--- (or `{on_done = function() ... end}`) to have it called once it's been said.
+--- Callbacks can't wait or ask things themselves. The command keeps going until they've all been called.
+--- @param message string What to say
+--- @param options table|function|nil `{wait = boolean, on_done = function}`, or just the `on_done` function
+function Mega.speak(message, options) end
+
+--- Speak a string, and wait until it's been said before carrying on.  
+--- Like `Mega.sleep`, the command waits in the background, so Mega goes back to listening for "mega"
+--- while it's talking, and the command picks up again once it's done and Mega isn't busy.
 --- @param message string What to say
-function Mega.speak(message) end
\ No newline at end of file
+function Mega.speak_and_wait(message) end
+
+--- Ask the user something, and wait for their answer. They don't need to say "mega" first.  
+--- The command is paused while Mega listens, and picks up right here once they've answered.
+--- Saying a cancel phrase (like "never mind") calls off the whole command instead.
+--- @param prompt string What to ask
+--- @return table|nil arguments Mega's best guess at the answer, like `Mega.arguments`, or nil if they didn't say anything
+--- @return table|nil raw_arguments All the guesses at the answer, like `Mega.raw_arguments`
+function Mega.ask(prompt) end
+
+--- Ask the user a yes or no question, and wait for their answer.  
+--- The ways to say yes and no are set in `mega.toml`.
+--- @param prompt string What to ask
+--- @return boolean|nil yes True if they said yes, false if they said no, or nil if they didn't say anything or it wasn't clear
+function Mega.confirm(prompt) end
+
+--- Wait a while before carrying on.  
+--- The command waits in the background, so Mega goes back to listening for "mega" in the meantime.
+--- It picks up again once the time's up and Mega isn't busy with anything else, so it might be a little late.
+--- Please use this instead of a busy loop!
+--- @param seconds number How long to wait
+function Mega.sleep(seconds) end
+
+--- Call a function once, after a while.  
+--- This keeps going after the command is done; the command sticks around until all its timers are finished.
+--- Timer callbacks can't wait or ask things themselves, and don't go off while Mega is listening
+--- for a command or an answer; they go off once it's done instead.
+--- @param seconds number How long to wait
+--- @param callback function What to call
+--- @return number timer An id to pass to `Mega.cancel`
+function Mega.after(seconds, callback) end
+
+--- Call a function over and over, every so often, until it's cancelled.  
+--- Like `Mega.after`, it keeps going after the command is done.
+--- @param seconds number How long to wait between calls
+--- @param callback function What to call
+--- @return number timer An id to pass to `Mega.cancel`
+function Mega.every(seconds, callback) end
+
+--- Stop a timer from `Mega.after` or `Mega.every`. It's fine if it's already gone off.
+--- @param timer number The id `Mega.after` or `Mega.every` gave back
+function Mega.cancel(timer) end
+
+--- Turn spoken words back into a number, like "one hundred and five" into 105.  
+--- This understands ordinals ("third", "twenty first"), decimals ("two point five") and "a dozen".
+--- @param words string|table The words, either as one string or a table of them
+--- @return number|nil number The number, or nil if the words aren't (all) a number
+--- @return boolean ordinal Whether it was an ordinal like "third" instead of "three"
+function Mega.parse_number(words) end
+
+--- Work out when a spoken time is, like "tomorrow at five pm", "in twenty minutes",
+--- "next tuesday" or "half past three".  
+--- Times without a day are the next time it'll be that time, so "at five" at 6 pm means tomorrow.
+--- @param words string|table The words, either as one string or a table of them
+--- @param now number|nil What time to count from, like `os.time()`. Leave it out to use the current time.
+--- @return number|nil time The time, in seconds like `os.time()` (so it works with `os.date`), or nil if the words aren't a time
+function Mega.parse_time(words, now) end
+
+--- Work out how long a spoken length of time is, like "twenty minutes" or "an hour and a half".
+--- @param words string|table The words, either as one string or a table of them
+--- @return number|nil seconds How many seconds long it is, or nil if the words aren't a length of time
+function Mega.parse_duration(words) end
+
+--- Read back something spelled out, like a name or a password.  
+--- Understands letters ("a bee see"), the NATO alphabet ("alpha bravo"), digits,
+--- "capital", "double" and a few symbols like "dash" and "at".
+--- @param words string|table The words as one string or a table of them, or a table of guesses like `Mega.raw_arguments`
+--- @return string|nil spelled What was spelled, or nil if some of it isn't spelling
+function Mega.decode_spelling(words) end
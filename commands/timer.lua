@@ -0,0 +1,12 @@
+-- "mega timer five minutes"
+local seconds = Mega.parse_duration(Mega.arguments)
+if not seconds then
+    Mega.speak("How long for?")
+    return
+end
+
+Mega.speak("Timer set.")
+-- This goes off after the command's done, so Mega can do other things in the meantime
+Mega.after(seconds, function()
+    Mega.speak("Time's up!")
+end)
@@ -0,0 +1,226 @@
+//! Automatic gain control for the microphone.
+//! Quiet people get turned up, loud people get turned down, and
+//! everybody gets told off if the mic is clipping.
+
+use std::fmt;
+
+/// The RMS level AGC tries to bring speech to
+const TARGET_RMS: f32 = 0.05;
+/// Never amplify more than this, no matter how quiet it is
+const MAX_GAIN: f32 = 20.0;
+/// Never attenuate more than this
+const MIN_GAIN: f32 = 0.25;
+/// Don't let the gain amplify background noise above this RMS level.
+/// Otherwise a silent room gets cranked up until it crosses the activation threshold.
+const NOISE_CEILING_RMS: f32 = 0.004;
+/// A chunk has to be this many times louder than the noise floor to count as speech
+const SPEECH_OVER_NOISE: f32 = 3.0;
+/// How fast the gain drops when things are too loud (0 to 1, per chunk)
+const ATTACK: f32 = 0.5;
+/// How fast the gain rises when things are too quiet (0 to 1, per chunk)
+const RELEASE: f32 = 0.05;
+/// How fast the noise floor is allowed to creep upwards (per chunk)
+const NOISE_FLOOR_RISE: f32 = 1.002;
+
+/// Samples at least this loud are counted as clipped
+const CLIP_LEVEL: f32 = 0.99;
+/// If more than this fraction of speech samples clip in a window, that window was clipping
+const CLIP_RATIO_WARNING: f32 = 0.005;
+/// If more than this fraction of speech samples are too quiet even at max gain, that window was too quiet
+const QUIET_RATIO_WARNING: f32 = 0.5;
+/// How long each window of level statistics is
+const WINDOW_SECONDS: f64 = 5.0;
+/// How many bad windows in a row before we complain
+const PERSISTENT_WINDOWS: u32 = 2;
+/// Don't nag the user more often than this
+const WARNING_COOLDOWN_SECONDS: f64 = 60.0;
+
+/// Something is wrong with the input level, and it's been wrong for a while.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelWarning {
+    /// The microphone is saturating
+    Clipping,
+    /// Even at max gain we can barely hear anything
+    TooQuiet,
+}
+
+impl LevelWarning {
+    /// Something short Mega can say out loud about it
+    pub fn hint(self) -> &'static str {
+        match self {
+            LevelWarning::Clipping => "You're a little loud. Try backing away from the microphone.",
+            LevelWarning::TooQuiet => "I can barely hear you. Try speaking up or moving closer.",
+        }
+    }
+}
+
+impl fmt::Display for LevelWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelWarning::Clipping => write!(f, "AGC: input is persistently clipping"),
+            LevelWarning::TooQuiet => write!(f, "AGC: input is persistently too quiet, even at max gain"),
+        }
+    }
+}
+
+/// Smoothly scales microphone audio towards `TARGET_RMS`.
+pub struct Agc {
+    /// The gain currently applied
+    gain: f32,
+    /// Our running guess at how loud the room is when nobody's talking
+    noise_floor: f32,
+
+    /// How many samples make up one statistics window
+    window_size: usize,
+    /// Samples seen so far in this window
+    window_samples: usize,
+    /// Speech samples seen so far in this window
+    speech_samples: usize,
+    /// Clipped samples seen so far in this window
+    clipped_samples: usize,
+    /// Speech samples that stayed too quiet even after gain
+    quiet_samples: usize,
+
+    /// How many windows in a row have been clipping
+    clipping_windows: u32,
+    /// How many windows in a row have been too quiet
+    quiet_windows: u32,
+    /// Samples left before we're allowed to warn again
+    cooldown: usize,
+    /// The warning waiting to be picked up, if any
+    warning: Option<LevelWarning>,
+}
+
+impl Agc {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            gain: 1.0,
+            noise_floor: NOISE_CEILING_RMS,
+            window_size: (sample_rate as f64 * WINDOW_SECONDS) as usize,
+            window_samples: 0,
+            speech_samples: 0,
+            clipped_samples: 0,
+            quiet_samples: 0,
+            clipping_windows: 0,
+            quiet_windows: 0,
+            cooldown: 0,
+            warning: None,
+        }
+    }
+
+    /// The gain currently being applied
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Run a chunk of mono microphone audio through the AGC.
+    pub fn process(&mut self, chunk: Vec<f32>) -> Vec<f32> {
+        if chunk.is_empty() {
+            return chunk;
+        }
+        let rms = (chunk.iter().map(|&s| s * s).sum::<f32>() / chunk.len() as f32).sqrt();
+
+        // Track the noise floor: drop to quiet chunks immediately, rise slowly.
+        if rms < self.noise_floor {
+            self.noise_floor = rms.max(f32::EPSILON);
+        } else {
+            self.noise_floor *= NOISE_FLOOR_RISE;
+        }
+        // The most we can turn it up without making silence sound like speech
+        let usable_max_gain = (NOISE_CEILING_RMS / self.noise_floor).clamp(1.0, MAX_GAIN);
+
+        let is_speech = rms > self.noise_floor * SPEECH_OVER_NOISE;
+        let old_gain = self.gain;
+        if is_speech {
+            let desired = (TARGET_RMS / rms).max(MIN_GAIN).min(usable_max_gain);
+            let speed = if desired < self.gain { ATTACK } else { RELEASE };
+            self.gain += (desired - self.gain) * speed;
+        } else {
+            // Don't let the gain sit above what the noise allows
+            self.gain = self.gain.min(usable_max_gain);
+        }
+
+        // Ramp from the old gain to the new one across the chunk so there's no click
+        let len = chunk.len() as f32;
+        let mut clipped = 0;
+        let out = chunk
+            .iter()
+            .enumerate()
+            .map(|(idx, &s)| {
+                let g = old_gain + (self.gain - old_gain) * (idx as f32 / len);
+                let amplified = s * g;
+                if s.abs() >= CLIP_LEVEL || amplified.abs() >= CLIP_LEVEL {
+                    clipped += 1;
+                }
+                amplified.clamp(-1.0, 1.0)
+            })
+            .collect();
+
+        if clipped > 0 && self.gain > 1.0 {
+            // We made it clip ourselves, so back off right away
+            self.gain = (self.gain * 0.5).max(1.0);
+        }
+
+        // Update statistics
+        self.window_samples += chunk.len();
+        self.clipped_samples += clipped;
+        if is_speech {
+            self.speech_samples += chunk.len();
+            let pinned = self.gain >= usable_max_gain * 0.95;
+            if pinned && rms * self.gain < TARGET_RMS / 4.0 {
+                self.quiet_samples += chunk.len();
+            }
+        }
+        self.cooldown = self.cooldown.saturating_sub(chunk.len());
+        if self.window_samples >= self.window_size {
+            self.end_window();
+        }
+
+        out
+    }
+
+    /// Pick up a level warning, if there is one waiting.
+    pub fn take_warning(&mut self) -> Option<LevelWarning> {
+        self.warning.take()
+    }
+
+    /// Tally up a statistics window and figure out if it's time to complain.
+    fn end_window(&mut self) {
+        // Windows where nobody talked don't tell us anything
+        if self.speech_samples > 0 {
+            let speech = self.speech_samples as f32;
+            if self.clipped_samples as f32 / speech > CLIP_RATIO_WARNING {
+                self.clipping_windows += 1;
+            } else {
+                self.clipping_windows = 0;
+            }
+            if self.quiet_samples as f32 / speech > QUIET_RATIO_WARNING {
+                self.quiet_windows += 1;
+            } else {
+                self.quiet_windows = 0;
+            }
+        }
+
+        if self.cooldown == 0 {
+            let warning = if self.clipping_windows >= PERSISTENT_WINDOWS {
+                Some(LevelWarning::Clipping)
+            } else if self.quiet_windows >= PERSISTENT_WINDOWS {
+                Some(LevelWarning::TooQuiet)
+            } else {
+                None
+            };
+            if warning.is_some() {
+                self.warning = warning;
+                self.clipping_windows = 0;
+                self.quiet_windows = 0;
+                self.cooldown =
+                    (self.window_size as f64 * WARNING_COOLDOWN_SECONDS / WINDOW_SECONDS) as usize;
+            }
+        }
+
+        self.window_samples = 0;
+        self.speech_samples = 0;
+        self.clipped_samples = 0;
+        self.quiet_samples = 0;
+    }
+}
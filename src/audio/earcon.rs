@@ -0,0 +1,159 @@
+//! Little beeps and boops, for when talking would take too long.
+//! Each event has a synthesized chime, which can be swapped out for a WAV file
+//! named after the event (like `ready.wav`) in the sounds folder.
+
+use audrey::read::Reader;
+use audrey::sample::{
+    interpolate::{Converter, Linear},
+    signal::{self, Signal},
+};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::path::Path;
+
+/// How loud earcons are, from 0 to 1
+const VOLUME: f32 = 0.3;
+/// Fade each note in and out over this long so it doesn't click
+const FADE_SECONDS: f32 = 0.01;
+
+/// Things Mega might want to make a noise about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Earcon {
+    /// Heard the wake phrase, listening for a command
+    Ready,
+    /// Heard a command, looking for it
+    Searching,
+    /// Found the command, running it
+    Executing,
+    /// Couldn't find the command
+    NotFound,
+    /// Gave up listening, or got cancelled
+    Dismissed,
+    /// Something broke
+    Error,
+}
+
+impl Earcon {
+    pub const ALL: [Earcon; 6] = [
+        Earcon::Ready,
+        Earcon::Searching,
+        Earcon::Executing,
+        Earcon::NotFound,
+        Earcon::Dismissed,
+        Earcon::Error,
+    ];
+
+    /// The name of the WAV file (minus `.wav`) that replaces this earcon
+    pub fn name(self) -> &'static str {
+        match self {
+            Earcon::Ready => "ready",
+            Earcon::Searching => "searching",
+            Earcon::Executing => "executing",
+            Earcon::NotFound => "not_found",
+            Earcon::Dismissed => "dismissed",
+            Earcon::Error => "error",
+        }
+    }
+
+    /// What Mega says for this event when feedback is spoken
+    pub fn phrase(self) -> &'static str {
+        match self {
+            Earcon::Ready => "ready",
+            Earcon::Searching => "searching for command",
+            Earcon::Executing => "Executing command",
+            Earcon::NotFound => "Could not find that command.",
+            Earcon::Dismissed => "never mind",
+            Earcon::Error => "Something went wrong.",
+        }
+    }
+
+    /// The built-in chime for this event
+    fn synthesize(self, sample_rate: u32) -> Vec<f32> {
+        match self {
+            Earcon::Ready => chime(&[523.25, 783.99], 0.09, sample_rate),
+            Earcon::Searching => chime(&[659.25], 0.07, sample_rate),
+            Earcon::Executing => chime(&[523.25, 659.25, 783.99], 0.06, sample_rate),
+            Earcon::NotFound => chime(&[392.0, 293.66], 0.15, sample_rate),
+            Earcon::Dismissed => chime(&[659.25, 440.0], 0.12, sample_rate),
+            Earcon::Error => chime(&[220.0, 0.0, 220.0], 0.1, sample_rate),
+        }
+    }
+}
+
+/// All the earcons, ready to go at the speaker's sample rate.
+pub struct Earcons {
+    sounds: HashMap<Earcon, Vec<f32>>,
+}
+
+impl Earcons {
+    /// Synthesize the earcons, replacing any that have a WAV file in `dir`.
+    pub fn load<P: AsRef<Path>>(dir: P, sample_rate: u32) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let mut sounds = HashMap::new();
+        for &earcon in Earcon::ALL.iter() {
+            let path = dir.join(format!("{}.wav", earcon.name()));
+            let sound = if path.is_file() {
+                println!("Using {:?} for the {:?} earcon", path, earcon);
+                load_wav(&path, sample_rate)?
+            } else {
+                earcon.synthesize(sample_rate)
+            };
+            sounds.insert(earcon, sound);
+        }
+        Ok(Self { sounds })
+    }
+
+    /// The audio for an earcon
+    pub fn get(&self, earcon: Earcon) -> Vec<f32> {
+        self.sounds.get(&earcon).cloned().unwrap_or_default()
+    }
+}
+
+/// Synthesize some notes (in Hz) one after another, each lasting `note_seconds`.
+/// A note of 0 Hz is a rest.
+pub fn chime(notes: &[f32], note_seconds: f32, sample_rate: u32) -> Vec<f32> {
+    let note_len = (note_seconds * sample_rate as f32) as usize;
+    let fade_len = ((FADE_SECONDS * sample_rate as f32) as usize).max(1);
+    notes
+        .iter()
+        .flat_map(|&frequency| {
+            (0..note_len).map(move |idx| {
+                let t = idx as f32 / sample_rate as f32;
+                // Fade in, fade out
+                let envelope = (idx.min(note_len - idx) as f32 / fade_len as f32).min(1.0);
+                // A little bit of the octave up makes it sound more like a chime than a beep
+                let wave = (2.0 * PI * frequency * t).sin() + 0.3 * (4.0 * PI * frequency * t).sin();
+                VOLUME * envelope * wave / 1.3
+            })
+        })
+        .collect()
+}
+
+/// Load a WAV file as mono audio at the given sample rate
+fn load_wav(path: &Path, sample_rate: u32) -> Result<Vec<f32>, String> {
+    let mut reader = Reader::open(path).map_err(|err| format!("{:?}: {}", path, err))?;
+    let description = reader.description();
+    let channels = (description.channel_count() as usize).max(1);
+    let samples = reader
+        .samples::<f32>()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("{:?}: {}", path, err))?;
+    let mono = samples
+        .chunks(channels)
+        .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
+        .collect::<Vec<_>>();
+
+    if description.sample_rate() == sample_rate {
+        return Ok(mono);
+    }
+    let sig = signal::from_iter(mono.into_iter().map(|s| [s]));
+    let interpolator = Linear::new([0.0], [0.0]);
+    let converter = Converter::from_hz_to_hz(
+        sig,
+        interpolator,
+        description.sample_rate() as f64,
+        sample_rate as f64,
+    );
+    Ok(converter.until_exhausted().map(|s| s[0]).collect())
+}
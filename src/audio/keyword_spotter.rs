@@ -0,0 +1,243 @@
+//! A tiny keyword spotter for the wake word.
+//! Running all of DeepSpeech on every noise is way too slow on a Pi,
+//! so instead this compares the microphone against a few recordings of the user
+//! saying the wake word (see `mega enroll`), using dynamic time warping on MFCCs.
+
+use super::mfcc::{self, Frame, Mfcc};
+
+use audrey::read::Reader;
+
+use std::fs;
+use std::path::Path;
+
+/// Ignore this long after a detection so one "mega" doesn't fire several times
+const REFRACTORY_SECONDS: f64 = 1.0;
+/// A match can be at most this many times longer or shorter than its template
+const MAX_WARP: f32 = 2.0;
+/// If there's only one template we can't calibrate, so use this average frame distance instead
+const FALLBACK_THRESHOLD: f32 = 10.0;
+
+/// One enrolled recording of the wake word
+struct Template {
+    frames: Vec<Frame>,
+    /// Accumulated cost of the best path ending at each template frame, as of the last input frame
+    costs: Vec<f32>,
+    /// How many steps long each of those paths is
+    lengths: Vec<u32>,
+    /// Which input frame each of those paths started on
+    starts: Vec<u64>,
+    /// How many input frames we've seen
+    now: u64,
+}
+
+impl Template {
+    fn new(frames: Vec<Frame>) -> Self {
+        let len = frames.len();
+        Self {
+            frames,
+            costs: vec![f32::INFINITY; len],
+            lengths: vec![0; len],
+            starts: vec![0; len],
+            now: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        for cost in self.costs.iter_mut() {
+            *cost = f32::INFINITY;
+        }
+        for length in self.lengths.iter_mut() {
+            *length = 0;
+        }
+    }
+
+    /// Step the streaming subsequence DTW forward by one input frame.
+    /// Returns the average cost per step of the best match ending right now, if there is a sensible one.
+    fn step(&mut self, input: &Frame) -> Option<f32> {
+        let len = self.frames.len();
+        let mut new_costs = vec![f32::INFINITY; len];
+        let mut new_lengths = vec![0; len];
+        let mut new_starts = vec![0; len];
+        for (i, template_frame) in self.frames.iter().enumerate() {
+            let d = mfcc::distance(input, template_frame);
+            // Candidate predecessors, as (cost, length, start)
+            let mut candidates = vec![(self.costs[i], self.lengths[i], self.starts[i])];
+            if i == 0 {
+                // A match can start at any time
+                candidates.push((0.0, 0, self.now));
+            } else {
+                candidates.push((self.costs[i - 1], self.lengths[i - 1], self.starts[i - 1]));
+                candidates.push((new_costs[i - 1], new_lengths[i - 1], new_starts[i - 1]));
+            }
+            // Pick whichever gives the lowest average cost
+            let best = candidates
+                .into_iter()
+                .filter(|(cost, _, _)| cost.is_finite())
+                .min_by(|a, b| {
+                    let avg_a = (a.0 + d) / (a.1 + 1) as f32;
+                    let avg_b = (b.0 + d) / (b.1 + 1) as f32;
+                    avg_a.partial_cmp(&avg_b).unwrap_or(std::cmp::Ordering::Equal)
+                });
+            if let Some((cost, length, start)) = best {
+                new_costs[i] = cost + d;
+                new_lengths[i] = length + 1;
+                new_starts[i] = start;
+            }
+        }
+        self.costs = new_costs;
+        self.lengths = new_lengths;
+        self.starts = new_starts;
+        self.now += 1;
+
+        let last = len - 1;
+        if !self.costs[last].is_finite() {
+            return None;
+        }
+        // Don't accept matches squished or stretched way out of shape
+        let duration = (self.now - self.starts[last]) as f32;
+        let ratio = duration / len as f32;
+        if (1.0 / MAX_WARP..=MAX_WARP).contains(&ratio) {
+            Some(self.costs[last] / self.lengths[last] as f32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Listens for the wake word by comparing against enrolled recordings.
+pub struct KeywordSpotter {
+    mfcc: Mfcc,
+    templates: Vec<Template>,
+    /// Matches with an average frame distance under this count as the wake word
+    threshold: f32,
+    /// Frames left to ignore after a detection
+    refractory: u32,
+    /// Frames to ignore after each detection
+    refractory_frames: u32,
+}
+
+impl KeywordSpotter {
+    /// Load all the `.wav` templates in the given folder.
+    /// `threshold_scale` is how much worse than the templates match each other a match is allowed to be.
+    /// Returns `Ok(None)` if there aren't any templates yet.
+    pub fn load<P: AsRef<Path>>(
+        dir: P,
+        sample_rate: u32,
+        threshold_scale: f32,
+    ) -> Result<Option<Self>, String> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            return Ok(None);
+        }
+
+        let mut templates = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|err| err.to_string())? {
+            let path = entry.map_err(|err| err.to_string())?.path();
+            if path.extension().is_none_or(|ext| ext != "wav") {
+                continue;
+            }
+            let mut reader = Reader::open(&path).map_err(|err| format!("{:?}: {}", path, err))?;
+            let description = reader.description();
+            let channels = description.channel_count() as usize;
+            let samples = reader
+                .samples::<f32>()
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("{:?}: {}", path, err))?;
+            // Mix down to mono
+            let mono = samples
+                .chunks(channels.max(1))
+                .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
+                .collect::<Vec<_>>();
+            let frames = mfcc::features(&mono, description.sample_rate());
+            if frames.is_empty() {
+                println!("Wake word template {:?} is too short; skipping it", path);
+                continue;
+            }
+            templates.push(Template::new(frames));
+        }
+        if templates.is_empty() {
+            return Ok(None);
+        }
+
+        // Calibrate by seeing how well the templates match each other
+        let mut cross_costs = Vec::new();
+        for (i, a) in templates.iter().enumerate() {
+            for (j, b) in templates.iter().enumerate() {
+                if i != j {
+                    cross_costs.push(dtw(&a.frames, &b.frames));
+                }
+            }
+        }
+        let threshold = if cross_costs.is_empty() {
+            FALLBACK_THRESHOLD
+        } else {
+            threshold_scale * cross_costs.iter().sum::<f32>() / cross_costs.len() as f32
+        };
+        println!(
+            "Loaded {} wake word templates (threshold {:.2})",
+            templates.len(),
+            threshold
+        );
+
+        let refractory_frames = (REFRACTORY_SECONDS / mfcc::HOP_SECONDS) as u32;
+        Ok(Some(Self {
+            mfcc: Mfcc::new(sample_rate),
+            templates,
+            threshold,
+            refractory: 0,
+            refractory_frames,
+        }))
+    }
+
+    /// Forget everything heard so far.
+    pub fn reset(&mut self) {
+        self.mfcc.reset();
+        for template in self.templates.iter_mut() {
+            template.reset();
+        }
+    }
+
+    /// Feed in some microphone audio.
+    /// Returns the match cost if the wake word was just heard.
+    pub fn push(&mut self, samples: &[f32]) -> Option<f32> {
+        let mut detection = None;
+        for frame in self.mfcc.push(samples) {
+            let best = self
+                .templates
+                .iter_mut()
+                .filter_map(|template| template.step(&frame))
+                .fold(f32::INFINITY, f32::min);
+            if self.refractory > 0 {
+                self.refractory -= 1;
+            } else if best < self.threshold {
+                detection = Some(best);
+                self.refractory = self.refractory_frames;
+            }
+        }
+        if detection.is_some() {
+            for template in self.templates.iter_mut() {
+                template.reset();
+            }
+        }
+        detection
+    }
+}
+
+/// Plain old DTW between two whole clips, as the average cost per step.
+fn dtw(a: &[Frame], b: &[Frame]) -> f32 {
+    let mut costs = vec![vec![(f32::INFINITY, 0u32); b.len() + 1]; a.len() + 1];
+    costs[0][0] = (0.0, 0);
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let d = mfcc::distance(&a[i - 1], &b[j - 1]);
+            let (cost, length) = [costs[i - 1][j - 1], costs[i - 1][j], costs[i][j - 1]]
+                .iter()
+                .cloned()
+                .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal))
+                .unwrap();
+            costs[i][j] = (cost + d, length + 1);
+        }
+    }
+    let (cost, length) = costs[a.len()][b.len()];
+    cost / length.max(1) as f32
+}
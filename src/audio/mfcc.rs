@@ -0,0 +1,202 @@
+//! Turns audio into MFCCs (mel-frequency cepstral coefficients).
+//! These are a compact fingerprint of what a slice of audio "sounds like",
+//! which is what the keyword spotter compares.
+
+use std::f32::consts::PI;
+
+/// How many coefficients each frame has.
+/// The 0th coefficient (overall loudness) is thrown out.
+pub const COEFFICIENTS: usize = 12;
+/// The MFCCs for one frame of audio
+pub type Frame = [f32; COEFFICIENTS];
+
+/// How long each frame of audio is
+const FRAME_SECONDS: f64 = 0.025;
+/// How far apart frames start
+pub const HOP_SECONDS: f64 = 0.010;
+/// How many triangular filters in the mel filterbank
+const MEL_FILTERS: usize = 26;
+/// Ignore frequencies above this; speech doesn't have much up there
+const MAX_FREQUENCY: f32 = 8000.0;
+/// Boosts the high frequencies a little before doing anything else
+const PRE_EMPHASIS: f32 = 0.97;
+
+/// Computes MFCCs from a stream of audio.
+pub struct Mfcc {
+    /// Samples per frame
+    frame_len: usize,
+    /// Samples between frame starts
+    hop_len: usize,
+    /// Size of the FFT. The frame gets zero-padded up to this.
+    fft_len: usize,
+    /// Hamming window
+    window: Vec<f32>,
+    /// For each mel filter, the FFT bins it covers and how much of each
+    filters: Vec<Vec<(usize, f32)>>,
+    /// Audio that hasn't made it into a whole frame yet
+    pending: Vec<f32>,
+    /// The last sample we saw, for pre-emphasis
+    last_sample: f32,
+}
+
+impl Mfcc {
+    pub fn new(sample_rate: u32) -> Self {
+        let frame_len = (sample_rate as f64 * FRAME_SECONDS) as usize;
+        let hop_len = (sample_rate as f64 * HOP_SECONDS) as usize;
+        let fft_len = frame_len.next_power_of_two();
+
+        let window = (0..frame_len)
+            .map(|n| 0.54 - 0.46 * (2.0 * PI * n as f32 / (frame_len - 1) as f32).cos())
+            .collect();
+
+        // Mel filters are evenly spaced on the mel scale
+        let to_mel = |hz: f32| 2595.0 * (1.0 + hz / 700.0).log10();
+        let from_mel = |mel: f32| 700.0 * (10f32.powf(mel / 2595.0) - 1.0);
+        let max_mel = to_mel(MAX_FREQUENCY.min(sample_rate as f32 / 2.0));
+        let bins = (0..MEL_FILTERS + 2)
+            .map(|i| {
+                let hz = from_mel(max_mel * i as f32 / (MEL_FILTERS + 1) as f32);
+                ((fft_len + 1) as f32 * hz / sample_rate as f32).floor() as usize
+            })
+            .collect::<Vec<_>>();
+        let filters = (0..MEL_FILTERS)
+            .map(|m| {
+                let (left, center, right) = (bins[m], bins[m + 1], bins[m + 2]);
+                (left..=right)
+                    .filter_map(|bin| {
+                        let weight = if bin < center {
+                            (bin - left) as f32 / (center - left).max(1) as f32
+                        } else {
+                            (right - bin) as f32 / (right - center).max(1) as f32
+                        };
+                        if weight > 0.0 {
+                            Some((bin, weight))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            frame_len,
+            hop_len,
+            fft_len,
+            window,
+            filters,
+            pending: Vec::new(),
+            last_sample: 0.0,
+        }
+    }
+
+    /// Forget any half-finished frame.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.last_sample = 0.0;
+    }
+
+    /// Feed in some audio, and get out all the frames that are now complete.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<Frame> {
+        for &sample in samples {
+            self.pending.push(sample - PRE_EMPHASIS * self.last_sample);
+            self.last_sample = sample;
+        }
+
+        let mut frames = Vec::new();
+        let mut start = 0;
+        while start + self.frame_len <= self.pending.len() {
+            frames.push(self.frame(&self.pending[start..start + self.frame_len]));
+            start += self.hop_len;
+        }
+        self.pending.drain(..start);
+        frames
+    }
+
+    /// Compute the MFCCs of exactly one frame of audio
+    fn frame(&self, samples: &[f32]) -> Frame {
+        let mut re = vec![0.0; self.fft_len];
+        let mut im = vec![0.0; self.fft_len];
+        for (idx, (&s, &w)) in samples.iter().zip(self.window.iter()).enumerate() {
+            re[idx] = s * w;
+        }
+        fft(&mut re, &mut im);
+
+        let log_energies = self
+            .filters
+            .iter()
+            .map(|filter| {
+                let energy = filter
+                    .iter()
+                    .map(|&(bin, weight)| weight * (re[bin] * re[bin] + im[bin] * im[bin]))
+                    .sum::<f32>()
+                    / self.fft_len as f32;
+                (energy + 1e-6).ln()
+            })
+            .collect::<Vec<_>>();
+
+        // DCT-II, skipping the 0th coefficient
+        let mut out = [0.0; COEFFICIENTS];
+        for (k, coeff) in out.iter_mut().enumerate() {
+            let k = k + 1;
+            *coeff = log_energies
+                .iter()
+                .enumerate()
+                .map(|(m, &e)| e * (PI * k as f32 * (m as f32 + 0.5) / MEL_FILTERS as f32).cos())
+                .sum();
+        }
+        out
+    }
+}
+
+/// Compute the MFCCs of a whole clip at once.
+pub fn features(samples: &[f32], sample_rate: u32) -> Vec<Frame> {
+    Mfcc::new(sample_rate).push(samples)
+}
+
+/// Euclidean distance between two frames
+pub fn distance(a: &Frame, b: &Frame) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// In-place iterative radix-2 FFT. The length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    // Butterflies
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -1,5 +1,9 @@
 //! Handles the audio
 
+pub mod agc;
+pub mod earcon;
+pub mod keyword_spotter;
+pub mod mfcc;
 pub mod speech_synthesis;
 
 use cpal::{
@@ -1,29 +1,88 @@
 //! Exposes a platform-agnostic API for speech synthesis.
 //! Wow that was a bunch of fancy developer words.
 
+use crossbeam::channel;
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
 #[cfg(target_os = "windows")]
 pub use windows_version::init;
+
+/// Something to say, and the number it'll be reported back as once it's been said
+pub struct Utterance {
+    pub id: u64,
+    pub text: String,
+}
+
+/// Sends things to the speech synthesizer.
+/// Clones all count up from the same place, so the ids never get mixed up.
+#[derive(Clone)]
+pub struct Voice {
+    sender: channel::Sender<Utterance>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Voice {
+    fn new(sender: channel::Sender<Utterance>) -> Self {
+        Self {
+            sender,
+            // 0 is never used, in case anyone wants it to mean "nothing"
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Say something. Things are said in order, one after another.
+    /// Returns an id that the synthesizer sends back once it's done saying it.
+    pub fn say<S>(&self, text: S) -> Result<u64, String>
+    where
+        S: Into<String>,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sender
+            .send(Utterance {
+                id,
+                text: text.into(),
+            })
+            .map_err(|err| err.to_string())?;
+        Ok(id)
+    }
+}
+
 #[cfg(target_os = "windows")]
 mod windows_version {
+    use super::{Utterance, Voice};
+
     use winapi::ctypes;
     use winapi::shared::{guiddef::CLSID, winerror};
     use winapi::um::{
         combaseapi::{self, CLSIDFromProgID, CoCreateInstance, CoInitializeEx},
         objbase::COINIT_MULTITHREADED,
-        sapi::ISpVoice,
+        sapi::{ISpVoice, SPF_ASYNC, SPF_IS_NOT_XML},
+        sapi51::{SPEI_END_INPUT_STREAM, SPEVENT, SPFEI},
         winnt::HRESULT,
     };
     use winapi::Interface;
 
     use crossbeam::channel;
 
+    use std::collections::VecDeque;
     use std::{mem, ptr::null_mut, thread};
 
-    pub fn init() -> Result<(channel::Sender<String>, thread::JoinHandle<()>), String> {
+    /// While it's talking, how long (in milliseconds) to wait to hear it's done with something
+    /// before checking if there's more to say
+    const EVENT_WAIT_MS: u32 = 50;
+
+    /// Returns the `Voice` to say things with, and a receiver that gets the id of everything
+    /// once it's been said.
+    pub fn init() -> Result<(Voice, channel::Receiver<u64>, thread::JoinHandle<()>), String> {
         println!("Initializing Windows speech synthesizer");
 
         // Abstract it away for the end user.
-        let (sender, receiver) = channel::unbounded::<String>();
+        let (sender, receiver) = channel::unbounded::<Utterance>();
+        let (spoken_sender, spoken_receiver) = channel::unbounded::<u64>();
         // Spin up a thread to handle the receiving
         let handle = thread::spawn(move || {
             // Intialize COM, which operates on the current thread FOREVER BWAHAHa
@@ -54,17 +113,76 @@ mod windows_version {
             .check(line!())
             .unwrap();
             let synther = unsafe { &mut *win_synther };
+            // Have SAPI let us know whenever it's done saying something
+            let interest = SPFEI(SPEI_END_INPUT_STREAM);
+            unsafe { synther.SetInterest(interest, interest) }
+                .check(line!())
+                .unwrap();
+            unsafe { synther.SetNotifyWin32Event() }
+                .check(line!())
+                .unwrap();
+
+            // What's still being said: our id, and SAPI's number for it
+            let mut saying: VecDeque<(u64, u32)> = VecDeque::new();
             loop {
-                let msg = receiver.recv().unwrap();
-                let wide_msg = msg.widen();
-                let ptr_to_wide = wide_msg.as_ptr(); // i think you have to pop out the pointer like this to ensure it isn't dropped
-                unsafe { synther.Speak(ptr_to_wide, 19, null_mut()) }
+                // If it's quiet there's nothing to hear back about, so just wait for something to say
+                let next = if saying.is_empty() {
+                    match receiver.recv() {
+                        Ok(utterance) => Some(utterance),
+                        // Mega's gone, so we can go too
+                        Err(channel::RecvError) => return,
+                    }
+                } else {
+                    match receiver.try_recv() {
+                        Ok(utterance) => Some(utterance),
+                        Err(channel::TryRecvError::Empty) => None,
+                        Err(channel::TryRecvError::Disconnected) => return,
+                    }
+                };
+                if let Some(utterance) = next {
+                    let wide_msg = utterance.text.widen();
+                    let ptr_to_wide = wide_msg.as_ptr(); // i think you have to pop out the pointer like this to ensure it isn't dropped
+                    let mut stream = 0;
+                    // Queue it up after whatever's being said now, instead of cutting it off
+                    let flags = SPF_ASYNC | SPF_IS_NOT_XML;
+                    unsafe { synther.Speak(ptr_to_wide, flags, &mut stream) }
+                        .check(line!())
+                        .unwrap();
+                    saying.push_back((utterance.id, stream));
+                    // There might be more to queue up
+                    continue;
+                }
+
+                // This succeeds either way, whether SAPI said something or it timed out
+                unsafe { synther.WaitForNotifyEvent(EVENT_WAIT_MS) }
                     .check(line!())
                     .unwrap();
+                loop {
+                    let mut event: SPEVENT = unsafe { mem::zeroed() };
+                    let mut fetched = 0;
+                    unsafe { synther.GetEvents(1, &mut event, &mut fetched) }
+                        .check(line!())
+                        .unwrap();
+                    if fetched == 0 {
+                        break;
+                    }
+                    if event.eEventId() != SPEI_END_INPUT_STREAM {
+                        continue;
+                    }
+                    // That's the end of this one, and anything before it
+                    while let Some(&(id, stream)) = saying.front() {
+                        if stream > event.ulStreamNum {
+                            break;
+                        }
+                        saying.pop_front();
+                        // Nobody listening is fine
+                        let _ = spoken_sender.send(id);
+                    }
+                }
             }
         });
 
-        Ok((sender, handle))
+        Ok((Voice::new(sender), spoken_receiver, handle))
     }
 
     trait TraitForHresultChecking {
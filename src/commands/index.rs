@@ -0,0 +1,1057 @@
+//! An in-memory copy of the `commands/` folder tree.
+//! It gets built once at startup and rebuilt whenever something in the folder changes,
+//! so finding a command never has to touch the disk.
+
+use super::manifest::{Manifest, ManifestEntry, MANIFEST_NAME};
+use super::paths::{self, Diagnostic};
+use crate::config::CommandsConfig;
+use crate::lattice::Lattice;
+use crate::phrases;
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Names of the command that runs when you name a folder but nothing in it, in order of preference
+pub const DEFAULT_NAMES: [&str; 2] = ["index", "_default"];
+
+/// The most commands to ask about at once when it's not clear which one they meant
+const MAX_CHOICES: usize = 3;
+/// How long to wait for the filesystem to settle down before re-indexing
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Stop following a branch of the tree once this little of the transcripts agree with it
+const MIN_SUPPORT: f64 = 1e-6;
+
+/// Something in the command tree
+#[derive(Debug, Clone)]
+pub enum Node {
+    Folder(Folder),
+    /// A Lua file to run
+    Command(PathBuf),
+}
+
+/// A folder of commands
+#[derive(Debug, Clone, Default)]
+pub struct Folder {
+    pub path: PathBuf,
+    /// Everything in the folder, keyed by the word you'd say to get at it
+    pub children: BTreeMap<String, Node>,
+    /// Aliases and such from `_mega.toml`
+    pub manifest: Manifest,
+    /// `index.lua` or `_default.lua`, which runs when you say the folder's name and nothing after
+    pub default: Option<PathBuf>,
+}
+
+/// A command that might be what the user said
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// The arguments start at this column
+    pub args_start: usize,
+    /// How much of the transcripts' weight agrees with this path, from 0 to 1
+    pub score: f64,
+    /// How much each transcript agrees with this path. These add up to `score`.
+    pub support: Vec<f64>,
+    /// If true, `path` is a folder with no default command, so there's nothing to run
+    pub is_folder: bool,
+    /// The names (or aliases) that lead to it, like `["game", "omnifactory"]`
+    pub words: Vec<String>,
+}
+
+impl Candidate {
+    /// How you'd say this command out loud
+    pub fn spoken(&self) -> String {
+        self.words.join(" ").replace(['_', '-'], " ")
+    }
+}
+
+/// What came of looking up a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Found a command!
+    Found(Candidate),
+    /// A few commands scored about the same, so we should ask which one they meant.
+    /// They're in order from best to worst.
+    Ambiguous(Vec<Candidate>),
+    /// Got as far as a folder, but didn't hear what to run in it and it has no default command.
+    /// `options` are the things you could say next.
+    Incomplete {
+        candidate: Candidate,
+        options: Vec<String>,
+    },
+    /// Nothing scored well enough. Here's the closest thing, if there was anything at all.
+    NotFound { best_guess: Option<Candidate> },
+}
+
+impl Folder {
+    /// Read a folder (and all its subfolders) off the disk.
+    /// Anything that can't be a command (or shouldn't be) gets skipped and reported in the diagnostics.
+    pub fn scan<P: AsRef<Path>>(path: P) -> Result<(Self, Vec<Diagnostic>), String> {
+        let path = path.as_ref();
+        let root = path
+            .canonicalize()
+            .map_err(|err| format!("{:?}: {}", path, err))?;
+        let mut diagnostics = Vec::new();
+        let mut visited = HashSet::new();
+        visited.insert(root.clone());
+        let folder = Folder::scan_inner(&root, &root, &mut visited, &mut diagnostics)?;
+        Ok((folder, diagnostics))
+    }
+
+    /// Read one folder in the tree.
+    /// `visited` has the real paths of all the folders so far, so links can't send us in circles.
+    fn scan_inner(
+        root: &Path,
+        path: &Path,
+        visited: &mut HashSet<PathBuf>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Self, String> {
+        let path = path.to_path_buf();
+        let manifest = Manifest::load(&path)?;
+        let mut children = BTreeMap::new();
+        let mut default = None;
+        let entries = fs::read_dir(&path).map_err(|err| format!("{:?}: {}", path, err))?;
+        // Go in a consistent order so collisions resolve the same way every time
+        let mut entries = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        for entry in entries {
+            if entry.file_name().is_some_and(|name| name == MANIFEST_NAME) {
+                continue;
+            }
+            // Follow links, but only if they stay inside the commands
+            let real = match paths::confine(root, &entry) {
+                Ok(real) => real,
+                Err(problem) => {
+                    diagnostics.push(Diagnostic::new(&entry, problem));
+                    continue;
+                }
+            };
+            let name = match entry.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_lowercase(),
+                None => {
+                    diagnostics.push(Diagnostic::new(&entry, "has a name that isn't valid text"));
+                    continue;
+                }
+            };
+            if !paths::is_valid_name(&name) {
+                diagnostics.push(Diagnostic::new(
+                    &entry,
+                    "has a name you can't say; use only letters, numbers, spaces, `_` and `-`",
+                ));
+                continue;
+            }
+            if manifest.is_disabled(&name) {
+                println!("{:?} is disabled", entry);
+                continue;
+            }
+            let is_lua = real.is_file() && entry.extension().is_some_and(|ext| ext == "lua");
+            if is_lua && DEFAULT_NAMES.contains(&name.as_str()) {
+                // Entries are sorted, so `_default` would come first; let `index` win
+                if default.is_some() && name != DEFAULT_NAMES[0] {
+                    continue;
+                }
+                if let Some(old) = default.replace(real) {
+                    diagnostics.push(Diagnostic::new(
+                        &entry,
+                        format!("is used as the default command instead of {:?}", old),
+                    ));
+                }
+                continue;
+            }
+            let node = if real.is_dir() {
+                if !visited.insert(real.clone()) {
+                    diagnostics.push(Diagnostic::new(
+                        &entry,
+                        "leads back to a folder that's already in the commands",
+                    ));
+                    continue;
+                }
+                Node::Folder(Folder::scan_inner(root, &real, visited, diagnostics)?)
+            } else if is_lua {
+                Node::Command(real)
+            } else {
+                diagnostics.push(Diagnostic::new(
+                    &entry,
+                    "is not a lua file, so it's ignored",
+                ));
+                continue;
+            };
+
+            match (children.get(&name), &node) {
+                (Some(Node::Folder(_)), Node::Command(_)) => {
+                    // Folders win, like they always have
+                    diagnostics.push(Diagnostic::new(
+                        &entry,
+                        "has the same name as a folder, which wins",
+                    ));
+                }
+                _ => {
+                    children.insert(name, node);
+                }
+            }
+        }
+        for name in manifest.entries.keys() {
+            let is_default = default.is_some() && DEFAULT_NAMES.contains(&name.as_str());
+            if !children.contains_key(name) && !is_default && !manifest.is_disabled(name) {
+                diagnostics.push(Diagnostic::new(
+                    path.join(MANIFEST_NAME),
+                    format!("mentions {:?}, but there's nothing by that name", name),
+                ));
+            }
+        }
+        Ok(Self {
+            path,
+            children,
+            manifest,
+            default,
+        })
+    }
+
+    /// Find the command that best fits what was said.
+    /// Anything scoring under `config.min_score` doesn't count,
+    /// and if the runners-up are close enough to the best, it's ambiguous.
+    pub fn resolve(&self, lattice: &Lattice, config: &CommandsConfig) -> Resolution {
+        let candidates = self.candidates(lattice, config.name_sensitivity);
+        for candidate in candidates.iter().take(3) {
+            println!(
+                "Candidate {:?} (score {:.3})",
+                candidate.path, candidate.score
+            );
+        }
+        let mut candidates = candidates.into_iter();
+        match candidates.next() {
+            Some(best) if best.score >= config.min_score && best.is_folder => {
+                let options = match self.find_folder(&best.path) {
+                    Some(folder) => folder.spoken_options(),
+                    None => Vec::new(),
+                };
+                Resolution::Incomplete {
+                    candidate: best,
+                    options,
+                }
+            }
+            Some(best) if best.score >= config.min_score => {
+                let mut close = vec![best.clone()];
+                for candidate in candidates {
+                    if close.len() >= MAX_CHOICES
+                        || candidate.score < config.min_score
+                        || candidate.score <= best.score * config.ambiguity
+                    {
+                        break;
+                    }
+                    // No point asking "did you mean time or time"
+                    if !candidate.is_folder
+                        && close.iter().all(|c| c.spoken() != candidate.spoken())
+                    {
+                        close.push(candidate);
+                    }
+                }
+                if close.len() > 1 {
+                    Resolution::Ambiguous(close)
+                } else {
+                    Resolution::Found(best)
+                }
+            }
+            best_guess => Resolution::NotFound { best_guess },
+        }
+    }
+
+    /// Split up several commands said in a row, like "time and then roll two dee six".
+    /// It only splits on a conjunction if what comes after it sounds like a command,
+    /// so "roll two and three" stays one command.
+    pub fn split(&self, lattice: &Lattice, config: &CommandsConfig) -> Vec<Lattice> {
+        let likely = lattice.likely_words();
+        let conjunctions = config
+            .conjunctions
+            .iter()
+            .map(|phrase| {
+                phrase
+                    .split_whitespace()
+                    .map(phrases::normalize)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|words| !words.is_empty())
+            .collect::<Vec<_>>();
+
+        // Find where the conjunctions are, as (start, end) column ranges
+        let mut joins = Vec::new();
+        let mut idx = 0;
+        while idx < likely.len() {
+            let longest = conjunctions
+                .iter()
+                .filter(|words| {
+                    words.iter().enumerate().all(|(offset, word)| {
+                        likely.get(idx + offset).is_some_and(|likely| {
+                            likely.as_deref().map(phrases::normalize).as_ref() == Some(word)
+                        })
+                    })
+                })
+                .map(Vec::len)
+                .max();
+            match longest {
+                Some(len) => {
+                    joins.push((idx, idx + len));
+                    idx += len;
+                }
+                None => idx += 1,
+            }
+        }
+
+        // Each segment is a range of columns; keep a split only if the next segment is a command
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for &(join_start, join_end) in joins.iter() {
+            let after = join_end..lattice.columns.len();
+            if after.is_empty() {
+                break;
+            }
+            let is_command = self
+                .candidates(&lattice.slice(after), config.name_sensitivity)
+                .first()
+                .is_some_and(|best| best.score >= config.min_score && !best.is_folder);
+            if is_command {
+                segments.push(start..join_start);
+                start = join_end;
+            }
+        }
+        segments.push(start..lattice.columns.len());
+        segments
+            .into_iter()
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| lattice.slice(segment))
+            .collect()
+    }
+
+    /// Score every command in the tree against all the transcripts, best first.
+    /// A command's score is how much of the transcripts' weight agrees with the way to it,
+    /// where each transcript's weight gets scaled down by how far off its words are from the names.
+    /// `name_sensitivity` is how alike (from 0 to 1) a word has to be to a name to count at all.
+    pub fn candidates(&self, lattice: &Lattice, name_sensitivity: f64) -> Vec<Candidate> {
+        let mut out = Vec::new();
+        self.collect_candidates(
+            lattice,
+            0,
+            &[],
+            lattice.weights.clone(),
+            name_sensitivity,
+            &mut out,
+        );
+
+        // Different transcripts can get to the same command different ways (an alias, skipping a column...).
+        // Each transcript counts once, for whichever way it agreed with most.
+        // The arguments start wherever the single best way said they do.
+        let mut merged: HashMap<PathBuf, (Candidate, f64)> = HashMap::new();
+        for candidate in out {
+            match merged.get_mut(&candidate.path) {
+                Some((existing, best_score)) => {
+                    if candidate.score > *best_score {
+                        *best_score = candidate.score;
+                        existing.args_start = candidate.args_start;
+                        existing.words = candidate.words.clone();
+                    }
+                    for (have, &new) in existing.support.iter_mut().zip(candidate.support.iter()) {
+                        *have = have.max(new);
+                    }
+                    existing.score = existing.support.iter().sum();
+                }
+                None => {
+                    let score = candidate.score;
+                    merged.insert(candidate.path.clone(), (candidate, score));
+                }
+            }
+        }
+
+        let mut out = merged.into_iter().map(|(_, (c, _))| c).collect::<Vec<_>>();
+        out.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                // Keep ties in a consistent order
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        out
+    }
+
+    /// Depth-first search down the tree.
+    /// `words` are the names on the way here,
+    /// and `support` is how much each transcript still agrees with the path so far.
+    fn collect_candidates(
+        &self,
+        lattice: &Lattice,
+        depth: usize,
+        words: &[String],
+        support: Vec<f64>,
+        name_sensitivity: f64,
+        out: &mut Vec<Candidate>,
+    ) {
+        let column = match lattice.columns.get(depth) {
+            Some(column) => column,
+            None => return,
+        };
+        // Try every alias and the name itself; any of them could be what they said
+        let spoken = self.children.iter().flat_map(|(name, node)| {
+            self.manifest
+                .spoken_names(name)
+                .into_iter()
+                .map(move |spoken| (spoken, node))
+        });
+        for (name, node) in spoken {
+            let name_joined = phrases::normalize(name);
+            // DeepSpeech likes to split up long words ("omni factory", "on the factory"),
+            // so let a name soak up a couple more columns than it has words
+            let max_span = name_word_count(name) + 2;
+            for span in 1..=max_span {
+                if depth + span > lattice.columns.len() {
+                    break;
+                }
+                let new_support = support
+                    .iter()
+                    .enumerate()
+                    .map(|(certainty_idx, &weight)| {
+                        if weight == 0.0 || column[certainty_idx].is_none() {
+                            return 0.0;
+                        }
+                        let heard = lattice.columns[depth..depth + span]
+                            .iter()
+                            .filter_map(|column| column[certainty_idx].as_ref())
+                            .map(|word| phrases::normalize(word))
+                            .collect::<String>();
+                        let similarity = phrases::word_similarity(&heard, &name_joined);
+                        if similarity >= name_sensitivity {
+                            weight * similarity
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let total: f64 = new_support.iter().sum();
+                if total < MIN_SUPPORT {
+                    // Nobody said this, don't bother going further
+                    continue;
+                }
+                let mut words = words.to_vec();
+                words.push(name.to_string());
+                match node {
+                    Node::Folder(folder) => {
+                        let mut below = Vec::new();
+                        folder.collect_candidates(
+                            lattice,
+                            depth + span,
+                            &words,
+                            new_support.clone(),
+                            name_sensitivity,
+                            &mut below,
+                        );
+                        // The transcripts that didn't go on to anything in the folder
+                        // go to its default command instead
+                        let leftover = new_support
+                            .iter()
+                            .enumerate()
+                            .map(|(certainty_idx, &weight)| {
+                                if below.iter().any(|c| c.support[certainty_idx] > 0.0) {
+                                    0.0
+                                } else {
+                                    weight
+                                }
+                            })
+                            .collect::<Vec<_>>();
+                        let leftover_total: f64 = leftover.iter().sum();
+                        out.append(&mut below);
+                        if leftover_total >= MIN_SUPPORT {
+                            out.push(Candidate {
+                                path: folder
+                                    .default
+                                    .clone()
+                                    .unwrap_or_else(|| folder.path.clone()),
+                                args_start: depth + span,
+                                score: leftover_total,
+                                support: leftover,
+                                is_folder: folder.default.is_none(),
+                                words,
+                            });
+                        }
+                    }
+                    Node::Command(path) => out.push(Candidate {
+                        path: path.clone(),
+                        args_start: depth + span,
+                        score: total,
+                        support: new_support,
+                        is_folder: false,
+                        words,
+                    }),
+                }
+            }
+        }
+
+        // Some transcripts might not have heard anything here (some other one heard an extra word),
+        // so try going right past this column with just them
+        let skipped = support
+            .iter()
+            .zip(column.iter())
+            .map(|(&weight, word)| if word.is_none() { weight } else { 0.0 })
+            .collect::<Vec<_>>();
+        if skipped.iter().sum::<f64>() >= MIN_SUPPORT {
+            self.collect_candidates(lattice, depth + 1, words, skipped, name_sensitivity, out);
+        }
+    }
+
+    /// Find a folder in here (or this one) by its path
+    pub fn find_folder(&self, path: &Path) -> Option<&Folder> {
+        if self.path == path {
+            return Some(self);
+        }
+        self.children.values().find_map(|node| match node {
+            Node::Folder(folder) if path.starts_with(&folder.path) => folder.find_folder(path),
+            _ => None,
+        })
+    }
+
+    /// What the manifest says about a command, wherever it is in here
+    pub fn manifest_entry(&self, path: &Path) -> Option<&ManifestEntry> {
+        if self.default.as_deref() == Some(path) {
+            // It's under whichever of the default names it's actually called
+            let name = path.file_stem()?.to_str()?;
+            return self.manifest.get(name);
+        }
+        self.children.iter().find_map(|(name, node)| match node {
+            Node::Command(command) if command == path => self.manifest.get(name),
+            Node::Folder(folder) => folder.manifest_entry(path),
+            _ => None,
+        })
+    }
+
+    /// What you could say to get at the things in this folder, leaving out hidden ones
+    pub fn spoken_options(&self) -> Vec<String> {
+        self.children
+            .keys()
+            .filter(|name| !self.manifest.is_hidden(name))
+            .map(|name| name.replace(['_', '-'], " "))
+            .collect()
+    }
+
+    /// Every command in here and in all the subfolders, with the words that lead to it.
+    pub fn commands(&self) -> Vec<(Vec<String>, PathBuf)> {
+        let mut out = Vec::new();
+        if let Some(default) = &self.default {
+            out.push((Vec::new(), default.clone()));
+        }
+        for (name, node) in self.children.iter() {
+            match node {
+                Node::Command(path) => out.push((vec![name.clone()], path.clone())),
+                Node::Folder(folder) => {
+                    for (mut words, path) in folder.commands() {
+                        words.insert(0, name.clone());
+                        out.push((words, path));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Writes this folder out as an indented list
+    fn write_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        if let Some(default) = &self.default {
+            writeln!(
+                f,
+                "{}(default: {:?})",
+                "  ".repeat(depth),
+                default.file_name().unwrap_or_default()
+            )?;
+        }
+        for (name, node) in self.children.iter() {
+            let mut notes = String::new();
+            if let Some(entry) = self.manifest.get(name) {
+                if !entry.aliases.is_empty() {
+                    notes.push_str(&format!(" ({})", entry.aliases.join(", ")));
+                }
+                if entry.hidden {
+                    notes.push_str(" [hidden]");
+                }
+            }
+            match node {
+                Node::Command(_) => writeln!(f, "{}{}{}", "  ".repeat(depth), name, notes)?,
+                Node::Folder(folder) => {
+                    writeln!(f, "{}{}/{}", "  ".repeat(depth), name, notes)?;
+                    folder.write_indented(f, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How many words are in a name, like 2 for `good_morning`
+fn name_word_count(name: &str) -> usize {
+    name.split(|c: char| c == '_' || c == '-' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .count()
+        .max(1)
+}
+
+impl fmt::Display for Folder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+/// The command tree, kept up to date with what's on disk.
+pub struct CommandIndex {
+    root: Folder,
+    /// Problems found the last time the tree was indexed
+    diagnostics: Vec<Diagnostic>,
+    /// Keep this around or it stops watching
+    _watcher: Option<RecommendedWatcher>,
+    /// Hears about changes from the watcher
+    events: mpsc::Receiver<DebouncedEvent>,
+}
+
+impl CommandIndex {
+    /// Index the given folder and start watching it for changes.
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, String> {
+        let root = root.as_ref();
+        let (sender, events) = mpsc::channel();
+        let watcher = match notify::watcher(sender, WATCH_DEBOUNCE) {
+            Ok(mut watcher) => match watcher.watch(root, RecursiveMode::Recursive) {
+                Ok(()) => Some(watcher),
+                Err(err) => {
+                    println!("Could not watch {:?} for changes: {}", root, err);
+                    None
+                }
+            },
+            Err(err) => {
+                println!("Could not watch {:?} for changes: {}", root, err);
+                None
+            }
+        };
+        let (root, diagnostics) = Folder::scan(root)?;
+        println!("Indexed {} commands", root.commands().len());
+        report(&diagnostics);
+        Ok(Self {
+            root,
+            diagnostics,
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// The whole tree
+    pub fn root(&self) -> &Folder {
+        &self.root
+    }
+
+    /// Where a command is, relative to the commands folder
+    pub fn relative_path<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        path.strip_prefix(&self.root.path).ok()
+    }
+
+    /// Make sure a command is still safe to run, and get its real path
+    pub fn check(&self, path: &Path) -> Result<PathBuf, String> {
+        paths::check_command(&self.root.path, path)
+    }
+
+    /// Re-index if anything changed on disk since last time.
+    /// Returns what changed; if it's everything, that's just the commands folder itself.
+    pub fn refresh(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for event in self.events.try_iter() {
+            match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path) => changed.push(path),
+                DebouncedEvent::Rename(from, to) => {
+                    changed.push(from);
+                    changed.push(to);
+                }
+                DebouncedEvent::Rescan => changed.push(self.root.path.clone()),
+                DebouncedEvent::Error(err, path) => {
+                    println!("Error watching the commands ({:?}): {}", path, err)
+                }
+                _ => {}
+            }
+        }
+        if !changed.is_empty() {
+            match Folder::scan(&self.root.path) {
+                Ok((root, diagnostics)) => {
+                    self.root = root;
+                    if diagnostics != self.diagnostics {
+                        report(&diagnostics);
+                    }
+                    self.diagnostics = diagnostics;
+                    println!(
+                        "Commands changed; re-indexed {} commands",
+                        self.root.commands().len()
+                    );
+                }
+                Err(err) => println!("Could not re-index the commands: {}", err),
+            }
+        }
+        changed
+    }
+}
+
+/// Mention that there are problems in the commands, without going on about it
+fn report(diagnostics: &[Diagnostic]) {
+    match diagnostics.len() {
+        0 => {}
+        1 => println!("Found a problem in the commands: {}", diagnostics[0]),
+        count => println!(
+            "Found {} problems in the commands; run `mega lint` to see them",
+            count
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::Transcript;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Makes a commands folder with empty files at the given paths
+    fn tree(files: &[&str]) -> Folder {
+        tree_with_manifest(files, "")
+    }
+
+    /// Makes a commands folder with a `_mega.toml` at the top
+    fn tree_with_manifest(files: &[&str], manifest: &str) -> Folder {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "mega-index-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+        }
+        if !manifest.is_empty() {
+            fs::write(root.join(MANIFEST_NAME), manifest).unwrap();
+        }
+        Folder::scan(&root).unwrap().0
+    }
+
+    /// Transcripts from most to least likely, with how confident DeepSpeech was in each
+    fn heard(transcripts: &[(&str, f64)]) -> Lattice {
+        let transcripts = transcripts
+            .iter()
+            .map(|&(text, confidence)| {
+                let words = text
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                // Every word takes half a second, so the same word lines up in every transcript
+                let times = (0..words.len())
+                    .map(|idx| (idx as f32 * 0.5, idx as f32 * 0.5 + 0.4))
+                    .collect();
+                Transcript {
+                    words,
+                    times,
+                    confidence,
+                }
+            })
+            .collect::<Vec<_>>();
+        Lattice::new(&transcripts)
+    }
+
+    fn found(resolution: Resolution) -> Candidate {
+        match resolution {
+            Resolution::Found(candidate) => candidate,
+            other => panic!("expected to find a command, got {:?}", other),
+        }
+    }
+
+    fn config(min_score: f64) -> CommandsConfig {
+        CommandsConfig {
+            min_score,
+            ..CommandsConfig::default()
+        }
+    }
+
+    #[test]
+    fn finds_commands_in_folders() {
+        let root = tree(&["time.lua", "game/omnifactory.lua", "game/notes.txt"]);
+        assert_eq!(root.commands().len(), 2);
+
+        let lattice = heard(&[("game omnifactory now", 0.0)]);
+        let best = found(root.resolve(&lattice, &config(0.5)));
+        assert!(best.path.ends_with("game/omnifactory.lua"));
+        assert_eq!(best.args_start, 2);
+        assert!((best.score - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn less_likely_transcripts_still_count() {
+        let root = tree(&["time.lua", "roll.lua"]);
+        let lattice = heard(&[("banana", 0.0), ("time", -1.0), ("pajamas", -2.0)]);
+        let best = found(root.resolve(&lattice, &config(0.2)));
+        assert!(best.path.ends_with("time.lua"));
+        assert!((best.score - lattice.weights[1]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transcripts_that_agree_add_up() {
+        let root = tree(&["time.lua", "roll.lua"]);
+        let lattice = heard(&[("roll", 0.0), ("time", -0.5), ("time", -0.6)]);
+        let candidates = root.candidates(&lattice, CommandsConfig::default().name_sensitivity);
+        assert!(candidates[0].path.ends_with("time.lua"));
+        assert!(candidates[1].path.ends_with("roll.lua"));
+    }
+
+    #[test]
+    fn weak_guesses_are_not_found() {
+        let root = tree(&["time.lua"]);
+        match root.resolve(&heard(&[("banana", 0.0)]), &config(0.5)) {
+            Resolution::NotFound { best_guess: None } => {}
+            other => panic!("{:?}", other),
+        }
+        let lattice = heard(&[("banana", 0.0), ("time", -20.0)]);
+        match root.resolve(&lattice, &config(0.5)) {
+            Resolution::NotFound {
+                best_guess: Some(guess),
+            } => assert!(guess.path.ends_with("time.lua")),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn close_enough_names_match() {
+        let root = tree(&["time.lua", "weather.lua"]);
+        let best = found(root.resolve(&heard(&[("whether", 0.0)]), &config(0.5)));
+        assert!(best.path.ends_with("weather.lua"));
+        assert!(best.score < 1.0);
+    }
+
+    #[test]
+    fn names_can_span_several_words() {
+        let root = tree(&["good_morning.lua", "game/omnifactory.lua"]);
+        let best = found(root.resolve(&heard(&[("good morning mega", 0.0)]), &config(0.5)));
+        assert!(best.path.ends_with("good_morning.lua"));
+        assert_eq!(best.args_start, 2);
+
+        // DeepSpeech splitting up a long word
+        let lattice = heard(&[("game omni factory", 0.0)]);
+        let best = found(root.resolve(&lattice, &config(0.5)));
+        assert!(best.path.ends_with("game/omnifactory.lua"));
+        assert_eq!(best.args_start, 3);
+    }
+
+    #[test]
+    fn aliases_lead_to_commands() {
+        let root = tree_with_manifest(
+            &["time.lua", "roll.lua"],
+            "[Time]\naliases = [\"clock\", \"what time is it\"]\n",
+        );
+        assert_eq!(
+            root.manifest.spoken_names("time"),
+            vec!["clock", "what time is it", "time"]
+        );
+
+        let best = found(root.resolve(&heard(&[("clock", 0.0)]), &config(0.5)));
+        assert!(best.path.ends_with("time.lua"));
+        let best = found(root.resolve(&heard(&[("what time is it", 0.0)]), &config(0.5)));
+        assert!(best.path.ends_with("time.lua"));
+        assert_eq!(best.args_start, 4);
+    }
+
+    #[test]
+    fn disabled_commands_are_left_out() {
+        let root = tree_with_manifest(&["time.lua", "roll.lua"], "[roll]\ndisabled = true\n");
+        assert!(!root.children.contains_key("roll"));
+        match root.resolve(&heard(&[("roll", 0.0)]), &config(0.5)) {
+            Resolution::NotFound { best_guess: None } => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_manifests_are_errors() {
+        let root = std::env::temp_dir().join(format!("mega-index-{}-bad", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(MANIFEST_NAME), "[time]\nalias = [\"clock\"]\n").unwrap();
+        assert!(Folder::scan(&root).is_err());
+    }
+
+    #[test]
+    fn folders_run_their_default() {
+        let root = tree(&[
+            "game/index.lua",
+            "game/_default.lua",
+            "game/omnifactory.lua",
+        ]);
+        let best = found(root.resolve(&heard(&[("game", 0.0)]), &config(0.5)));
+        assert!(best.path.ends_with("game/index.lua"));
+        assert_eq!(best.args_start, 1);
+        // Naming something in the folder still gets you that
+        let best = found(root.resolve(&heard(&[("game omnifactory", 0.0)]), &config(0.5)));
+        assert!(best.path.ends_with("game/omnifactory.lua"));
+    }
+
+    #[test]
+    fn defaults_use_their_own_manifest_entry() {
+        let root = tree_with_manifest(
+            &["_default.lua", "time.lua"],
+            "[index]\nhidden = true\n\n[_default]\naliases = [\"hello\"]\n",
+        );
+        let default = root.default.clone().unwrap();
+        let entry = root.manifest_entry(&default).unwrap();
+        assert_eq!(entry.aliases, vec!["hello"]);
+        assert!(!entry.hidden);
+    }
+
+    #[test]
+    fn folders_without_a_default_list_what_is_in_them() {
+        let root = tree(&[
+            "game/omnifactory.lua",
+            "game/sky_factory.lua",
+            "game/secret.lua",
+        ]);
+        let game = match root.children.get("game") {
+            Some(Node::Folder(folder)) => folder.path.clone(),
+            other => panic!("{:?}", other),
+        };
+        fs::write(game.join(MANIFEST_NAME), "[secret]\nhidden = true\n").unwrap();
+        let (root, _) = Folder::scan(&root.path).unwrap();
+        match root.resolve(&heard(&[("game", 0.0)]), &config(0.5)) {
+            Resolution::Incomplete { candidate, options } => {
+                assert!(candidate.is_folder);
+                assert_eq!(candidate.path, game);
+                assert_eq!(options, vec!["omnifactory", "sky factory"]);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn asks_when_it_is_a_toss_up() {
+        let root = tree(&["time.lua", "roll.lua", "weather.lua"]);
+        let lattice = heard(&[("roll", 0.0), ("time", -0.1), ("weather", -10.0)]);
+        match root.resolve(&lattice, &config(0.1)) {
+            Resolution::Ambiguous(choices) => {
+                let spoken = choices.iter().map(Candidate::spoken).collect::<Vec<_>>();
+                assert_eq!(spoken, vec!["roll", "time"]);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // Turning the ambiguity up past 1 means never ask
+        let never = CommandsConfig {
+            ambiguity: 1.1,
+            ..config(0.1)
+        };
+        let best = found(root.resolve(&lattice, &never));
+        assert!(best.path.ends_with("roll.lua"));
+    }
+
+    #[test]
+    fn time_is_not_mistaken_for_timer() {
+        // "time" sounds just like the start of "timer", so this needs the real commands
+        let (root, _) = Folder::scan(crate::commands::COMMANDS_ROOT).unwrap();
+        let config = CommandsConfig::default();
+        let best = found(root.resolve(&heard(&[("time", 0.0)]), &config));
+        assert!(best.path.ends_with("time.lua"));
+        let best = found(root.resolve(&heard(&[("timer", 0.0)]), &config));
+        assert!(best.path.ends_with("timer.lua"));
+    }
+
+    #[test]
+    fn does_not_ask_when_one_is_clearly_better() {
+        let root = tree(&["time.lua", "roll.lua"]);
+        let lattice = heard(&[("roll", 0.0), ("time", -5.0)]);
+        let best = found(root.resolve(&lattice, &config(0.1)));
+        assert_eq!(best.spoken(), "roll");
+    }
+
+    #[test]
+    fn spoken_names_sound_like_speech() {
+        let root = tree(&["game/sky_factory.lua"]);
+        let best = found(root.resolve(&heard(&[("game sky factory", 0.0)]), &config(0.5)));
+        assert_eq!(best.words, vec!["game", "sky_factory"]);
+        assert_eq!(best.spoken(), "game sky factory");
+    }
+
+    #[test]
+    fn problems_are_reported_and_skipped() {
+        let root = tree(&["time.lua", "notes.txt", "what's up.lua", "game/time.lua"]);
+        let (root, diagnostics) = Folder::scan(&root.path).unwrap();
+        assert_eq!(root.commands().len(), 2);
+        let problems = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(problems, vec!["notes.txt", "what's up.lua"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_cannot_leave_the_commands() {
+        use std::os::unix::fs::symlink;
+
+        let outside = tree(&["evil.lua"]);
+        let root = tree(&["time.lua", "game/omnifactory.lua"]);
+        symlink(outside.path.join("evil.lua"), root.path.join("evil.lua")).unwrap();
+        symlink(&outside.path, root.path.join("elsewhere")).unwrap();
+        // A link back up the tree would go around in circles
+        symlink(&root.path, root.path.join("game").join("again")).unwrap();
+        // Links that stay inside are fine
+        symlink(root.path.join("time.lua"), root.path.join("clock.lua")).unwrap();
+
+        let (root, diagnostics) = Folder::scan(&root.path).unwrap();
+        let names = root.children.keys().collect::<Vec<_>>();
+        assert_eq!(names, vec!["clock", "game", "time"]);
+        assert_eq!(diagnostics.len(), 3, "{:?}", diagnostics);
+        assert!(paths::check_command(&root.path, &outside.path.join("evil.lua")).is_err());
+        let clock = found(root.resolve(&heard(&[("clock", 0.0)]), &config(0.5)));
+        assert!(clock.path.ends_with("time.lua"));
+    }
+
+    /// What each command said in a row sounds like
+    fn split(root: &Folder, text: &str) -> Vec<String> {
+        root.split(&heard(&[(text, 0.0)]), &config(0.5))
+            .iter()
+            .map(|lattice| {
+                lattice
+                    .likely_words()
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_commands_said_in_a_row() {
+        let root = tree(&["time.lua", "roll.lua", "weather.lua"]);
+        assert_eq!(
+            split(&root, "time and then roll two dee six"),
+            vec!["time", "roll two dee six"]
+        );
+        assert_eq!(
+            split(&root, "weather then time and roll"),
+            vec!["weather", "time", "roll"]
+        );
+    }
+
+    #[test]
+    fn only_splits_before_commands() {
+        let root = tree(&["time.lua", "roll.lua"]);
+        assert_eq!(
+            split(&root, "roll two and three"),
+            vec!["roll two and three"]
+        );
+        assert_eq!(split(&root, "roll two and"), vec!["roll two and"]);
+        assert_eq!(split(&root, "and time"), vec!["time"]);
+    }
+}
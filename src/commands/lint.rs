@@ -0,0 +1,38 @@
+//! `mega lint`: everything that's wrong with the commands folder, all in one place.
+
+use super::index::Folder;
+use super::paths::Diagnostic;
+
+use rlua::Lua;
+
+use std::fs;
+use std::path::Path;
+
+/// Index the commands and try compiling every one of them (without running it).
+pub fn lint<P: AsRef<Path>>(root: P) -> Result<Vec<Diagnostic>, String> {
+    let (folder, mut diagnostics) = Folder::scan(root)?;
+
+    let lua = Lua::new();
+    for (_, path) in folder.commands() {
+        let source = match fs::read(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                diagnostics.push(Diagnostic::new(
+                    &path,
+                    format!("could not be read: {}", err),
+                ));
+                continue;
+            }
+        };
+        let compiled = lua.context(|ctx| {
+            ctx.load(&source)
+                .set_name(&*path.to_string_lossy())?
+                .into_function()
+                .map(|_| ())
+        });
+        if let Err(err) = compiled {
+            diagnostics.push(Diagnostic::new(&path, format!("does not compile: {}", err)));
+        }
+    }
+    Ok(diagnostics)
+}
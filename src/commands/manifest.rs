@@ -0,0 +1,97 @@
+//! `_mega.toml` files, which say how to get at the commands in a folder.
+//!
+//! ```toml
+//! [time]
+//! aliases = ["clock", "what time is it"]
+//!
+//! [predict]
+//! hidden = true
+//!
+//! [roll]
+//! slots = ["count: number?", "dee", "sides: number"]
+//!
+//! [backup]
+//! allow = ["files", "processes"]
+//! ```
+
+use crate::parsing::slots::Schema;
+use crate::sandbox::Capability;
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What the manifest in each folder is called
+pub const MANIFEST_NAME: &str = "_mega.toml";
+
+/// Settings for one command or subfolder
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// Other things you can say to get at it. These can be multiple words.
+    pub aliases: Vec<String>,
+    /// Still works, but doesn't show up in listings
+    pub hidden: bool,
+    /// Acts like it isn't there at all
+    pub disabled: bool,
+    /// What the command expects to hear after its name; see `parsing::slots`
+    pub slots: Option<Schema>,
+    /// Things it's allowed to do that commands can't usually, like touch files
+    pub allow: Vec<Capability>,
+}
+
+/// A folder's manifest, keyed by the name of the command (without `.lua`) or subfolder.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Manifest {
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    /// Load the manifest in the given folder.
+    /// No manifest is fine; you get an empty one.
+    pub fn load<P: AsRef<Path>>(folder: P) -> Result<Self, String> {
+        let path = folder.as_ref().join(MANIFEST_NAME);
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                let manifest: Manifest =
+                    toml::from_str(&text).map_err(|err| format!("{:?}: {}", path, err))?;
+                // The names should be lowercase like everything else
+                let entries = manifest
+                    .entries
+                    .into_iter()
+                    .map(|(name, entry)| (name.to_lowercase(), entry))
+                    .collect();
+                Ok(Self { entries })
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{:?}: {}", path, err)),
+        }
+    }
+
+    /// The entry for a command or subfolder, if the manifest says anything about it
+    pub fn get(&self, name: &str) -> Option<&ManifestEntry> {
+        self.entries.get(name)
+    }
+
+    /// Everything you can say to get at `name`: its aliases first, then the name itself.
+    pub fn spoken_names<'a>(&'a self, name: &'a str) -> Vec<&'a str> {
+        let mut names = match self.get(name) {
+            Some(entry) => entry.aliases.iter().map(String::as_str).collect(),
+            None => Vec::new(),
+        };
+        names.push(name);
+        names
+    }
+
+    pub fn is_hidden(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|entry| entry.hidden)
+    }
+
+    pub fn is_disabled(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|entry| entry.disabled)
+    }
+}
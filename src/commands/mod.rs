@@ -0,0 +1,11 @@
+//! Finding the Lua command files that go with what the user said.
+
+pub mod index;
+pub mod lint;
+pub mod manifest;
+pub mod paths;
+
+pub use index::{Candidate, CommandIndex, Resolution};
+
+/// The folder all the commands live in
+pub const COMMANDS_ROOT: &str = "commands";
@@ -0,0 +1,99 @@
+//! Keeping command lookups inside the commands folder.
+//! Nothing that gets run should come from outside it, whether by a link, `..`, or a weird name.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Something wrong with a file in the commands folder
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub problem: String,
+}
+
+impl Diagnostic {
+    pub fn new<P: AsRef<Path>, S: Into<String>>(path: P, problem: S) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            problem: problem.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.path, self.problem)
+    }
+}
+
+/// Can you say this name out loud?
+/// Only letters, numbers, spaces, `_` and `-` are allowed, so no separators, dots or `..`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.trim().is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == ' ')
+}
+
+/// Where `path` really is, following any links.
+/// It's an error if that's outside `root` (which should already be canonicalized).
+pub fn confine(root: &Path, path: &Path) -> Result<PathBuf, String> {
+    let real = path
+        .canonicalize()
+        .map_err(|err| format!("can't be found: {}", err))?;
+    if real.starts_with(root) {
+        Ok(real)
+    } else {
+        Err(format!("leads outside the commands folder, to {:?}", real))
+    }
+}
+
+/// Make sure `path` is still a Lua file inside `root`, right before running it.
+/// Things might have moved since the commands were indexed.
+pub fn check_command(root: &Path, path: &Path) -> Result<PathBuf, String> {
+    let real = confine(root, path)?;
+    if !real.is_file() {
+        return Err("is not a file anymore".to_string());
+    }
+    if real.extension().is_none_or(|ext| ext != "lua") {
+        return Err("is not a lua file".to_string());
+    }
+    Ok(real)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn names_you_can_say() {
+        assert!(is_valid_name("time"));
+        assert!(is_valid_name("sky_factory"));
+        assert!(is_valid_name("good-morning"));
+        assert!(is_valid_name("what time is it"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("  "));
+        assert!(!is_valid_name(".."));
+        assert!(!is_valid_name("game/time"));
+        assert!(!is_valid_name("time.old"));
+    }
+
+    #[test]
+    fn commands_have_to_stay_put() {
+        let root = std::env::temp_dir().join(format!("mega-paths-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("game")).unwrap();
+        fs::write(root.join("time.lua"), "").unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert!(check_command(&root, &root.join("time.lua")).is_ok());
+        assert!(check_command(&root, &root.join("game/../time.lua")).is_ok());
+        assert!(check_command(&root, &root.join("notes.txt")).is_err());
+        assert!(check_command(&root, &root.join("game")).is_err());
+        assert!(check_command(&root, &root.join("gone.lua")).is_err());
+        assert!(check_command(&root.join("game"), &root.join("game/../time.lua")).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+//! Loads Mega's settings from `mega.toml`.
+//! Everything has a default, so the file (and any field in it) can be left out.
+
+use serde::Deserialize;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where Mega looks for its settings
+pub const CONFIG_PATH: &str = "mega.toml";
+/// The most `sandbox.max_seconds` can be. That's a long time to leave a command alone.
+const MAX_SANDBOX_SECONDS: f64 = 24.0 * 60.0 * 60.0;
+
+/// All of Mega's settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub wake: WakeConfig,
+    pub spotter: SpotterConfig,
+    pub listen: ListenConfig,
+    pub feedback: FeedbackConfig,
+    pub commands: CommandsConfig,
+    pub store: StoreConfig,
+    pub sandbox: SandboxConfig,
+}
+
+/// Settings for listening for the wake phrase.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WakeConfig {
+    /// Phrases that wake Mega up. These can be multiple words, like "hey mega".
+    pub phrases: Vec<String>,
+    /// How closely a transcript has to match a wake phrase, from 0 to 1.
+    /// 1 means it must be spelled exactly; lower numbers let sound-alikes through.
+    pub sensitivity: f64,
+}
+
+impl Default for WakeConfig {
+    fn default() -> Self {
+        Self {
+            phrases: vec!["mega".to_string()],
+            sensitivity: 0.75,
+        }
+    }
+}
+
+/// Settings for the lightweight wake word spotter.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpotterConfig {
+    /// Use the keyword spotter instead of DeepSpeech to listen for the wake word.
+    /// It only kicks in once there are some recordings in `templates`.
+    pub enabled: bool,
+    /// Folder holding the recordings made by `mega enroll`
+    pub templates: String,
+    /// How much worse than the recordings match each other a match can be.
+    /// Bigger is more forgiving (and more trigger-happy).
+    pub threshold_scale: f32,
+    /// How many recordings `mega enroll` makes
+    pub enroll_count: u32,
+}
+
+impl Default for SpotterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            templates: "resources/wake_templates".to_string(),
+            threshold_scale: 1.3,
+            enroll_count: 3,
+        }
+    }
+}
+
+/// Settings for listening for a command after the wake phrase.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    /// Give up and go back to sleep if nobody says anything for this long
+    pub timeout_seconds: f64,
+    /// Saying one of these instead of a command calls the whole thing off
+    pub cancel_phrases: Vec<String>,
+    /// How closely a transcript has to match a cancel phrase, from 0 to 1
+    pub cancel_sensitivity: f64,
+    /// Ways to say yes when a command asks with `Mega.confirm`
+    pub yes_phrases: Vec<String>,
+    /// ... and ways to say no
+    pub no_phrases: Vec<String>,
+    /// How closely a transcript has to match a yes or no phrase, from 0 to 1.
+    /// These are short words, so this is stricter than for cancelling, or "now" would count as "no".
+    pub yes_no_sensitivity: f64,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: 10.0,
+            cancel_phrases: vec![
+                "never mind".to_string(),
+                "cancel".to_string(),
+                "forget it".to_string(),
+            ],
+            cancel_sensitivity: 0.8,
+            yes_phrases: vec![
+                "yes".to_string(),
+                "yeah".to_string(),
+                "yep".to_string(),
+                "sure".to_string(),
+                "okay".to_string(),
+                "do it".to_string(),
+            ],
+            no_phrases: vec![
+                "no".to_string(),
+                "nope".to_string(),
+                "nah".to_string(),
+                "don't".to_string(),
+            ],
+            yes_no_sensitivity: 0.9,
+        }
+    }
+}
+
+/// How Mega lets you know what it's doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedbackStyle {
+    /// Say it out loud, like "searching for command"
+    Spoken,
+    /// Play a short chime
+    Earcon,
+    /// Both of the above
+    Both,
+}
+
+/// Settings for feedback on what Mega is doing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedbackConfig {
+    pub style: FeedbackStyle,
+    /// Folder of WAV files to use instead of the built-in chimes, like `ready.wav`
+    pub sounds: String,
+}
+
+impl Default for FeedbackConfig {
+    fn default() -> Self {
+        Self {
+            style: FeedbackStyle::Earcon,
+            sounds: "resources/earcons".to_string(),
+        }
+    }
+}
+
+/// Settings for finding commands.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandsConfig {
+    /// How sure Mega has to be that it heard a command right before running it, from 0 to 1.
+    /// This is the share of all the transcripts that agree on the command.
+    pub min_score: f64,
+    /// How closely what was heard has to match a command or folder name, from 0 to 1.
+    /// 1 means it must be spelled exactly; lower numbers let sound-alikes through.
+    pub name_sensitivity: f64,
+    /// If the next best command scores more than this much of the best one (from 0 to 1),
+    /// Mega asks which one you meant instead of guessing.
+    pub ambiguity: f64,
+    /// Words that go between commands said in a row, like "time and then roll two dee six"
+    pub conjunctions: Vec<String>,
+    /// Turn a command off after it goes wrong this many times in a row, until its file changes.
+    /// 0 means never.
+    pub max_failures: u32,
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self {
+            min_score: 0.1,
+            name_sensitivity: 0.7,
+            ambiguity: 0.8,
+            conjunctions: vec![
+                "and".to_string(),
+                "then".to_string(),
+                "and then".to_string(),
+                "after that".to_string(),
+            ],
+            max_failures: 3,
+        }
+    }
+}
+
+/// Settings for where commands keep things between runs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// Folder holding each command's store
+    pub folder: String,
+    /// How big each command's store can get, in bytes
+    pub max_bytes: usize,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            folder: "store".to_string(),
+            max_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Limits on how much a command can do before Mega stops it.
+/// They count from when the command last stopped to wait for something, like with `Mega.sleep`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    /// How many Lua instructions a command can run without a break
+    pub max_instructions: u64,
+    /// How long a command can run without a break, in seconds
+    pub max_seconds: f64,
+    /// How much memory a command can use altogether, in bytes
+    pub max_memory_bytes: usize,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            max_instructions: 100_000_000,
+            max_seconds: 5.0,
+            max_memory_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from the given path.
+    /// If there's no file there, you get the defaults.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let config: Self =
+                    toml::from_str(&text).map_err(|err| format!("{:?}: {}", path, err))?;
+                config
+                    .check()
+                    .map_err(|err| format!("{:?}: {}", path, err))?;
+                Ok(config)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                println!("No config at {:?}; using the defaults", path);
+                Ok(Self::default())
+            }
+            Err(err) => Err(format!("{:?}: {}", path, err)),
+        }
+    }
+
+    /// Catch settings that would break things later on
+    fn check(&self) -> Result<(), String> {
+        let seconds = self.sandbox.max_seconds;
+        if !(0.0..=MAX_SANDBOX_SECONDS).contains(&seconds) {
+            return Err(format!(
+                "sandbox.max_seconds should be between 0 and {}, not {}",
+                MAX_SANDBOX_SECONDS, seconds
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(text: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(text).map_err(|err| err.to_string())?;
+        config.check().map(|_| config)
+    }
+
+    #[test]
+    fn defaults_are_fine() {
+        assert!(Config::default().check().is_ok());
+        let config = load("[sandbox]\nmax_seconds = 2.5\n").unwrap();
+        assert_eq!(config.sandbox.max_seconds, 2.5);
+        assert_eq!(config.sandbox.max_instructions, 100_000_000);
+    }
+
+    #[test]
+    fn sandbox_time_has_to_make_sense() {
+        for &seconds in &["-1.0", "inf", "nan", "1e30"] {
+            let text = format!("[sandbox]\nmax_seconds = {}\n", seconds);
+            assert!(load(&text).is_err(), "{}", seconds);
+        }
+    }
+}
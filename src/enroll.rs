@@ -0,0 +1,125 @@
+//! `mega enroll`: records the user saying the wake word a few times,
+//! so the keyword spotter has something to compare against.
+
+use crate::audio::{self, agc::Agc};
+use crate::config::{self, Config};
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+/// How loud (RMS, after AGC) a chunk has to be to count as the start of speech
+const START_RMS: f32 = 0.02;
+/// Stop recording after it's been quiet this long
+const END_SILENCE_SECONDS: f64 = 0.5;
+/// Stop recording after this long no matter what
+const MAX_RECORDING_SECONDS: f64 = 3.0;
+/// Keep this much audio from before the speech started
+const PRE_ROLL_SECONDS: f64 = 0.1;
+
+/// Record the wake word `spotter.enroll_count` times and save the clips as templates.
+pub fn run() -> Result<(), String> {
+    let config = Config::load(config::CONFIG_PATH)?;
+    let dir = PathBuf::from(&config.spotter.templates);
+    fs::create_dir_all(&dir).map_err(|err| format!("{:?}: {}", dir, err))?;
+    let phrase = config
+        .wake
+        .phrases
+        .first()
+        .map(|p| p.as_str())
+        .unwrap_or("the wake word");
+
+    // The speaker bits have to stay alive even though we don't use them
+    let ((_speaker_sender, _speaker_handle, _), (mic_receiver, _mic_handle, sample_rate)) =
+        audio::get_audio_channels();
+    let mut agc = Agc::new(sample_rate);
+
+    let count = config.spotter.enroll_count;
+    for n in 1..=count {
+        println!("({}/{}) Say \"{}\"...", n, count, phrase);
+        // Throw out anything heard while we weren't listening
+        mic_receiver.try_iter().for_each(drop);
+
+        let clip = record_utterance(&mic_receiver, &mut agc, sample_rate)?;
+        let path = next_free_path(&dir);
+        save_wav(&path, &clip, sample_rate)?;
+        println!(
+            "Saved {:.2} seconds to {:?}",
+            clip.len() as f64 / sample_rate as f64,
+            path
+        );
+    }
+    println!("All done! Restart Mega to start using the new recordings.");
+    Ok(())
+}
+
+/// Wait for someone to start talking, then record until they stop.
+fn record_utterance(
+    mic_receiver: &mpsc::Receiver<Vec<f32>>,
+    agc: &mut Agc,
+    sample_rate: u32,
+) -> Result<Vec<f32>, String> {
+    let pre_roll_size = (sample_rate as f64 * PRE_ROLL_SECONDS) as usize;
+    let end_silence_size = (sample_rate as f64 * END_SILENCE_SECONDS) as usize;
+    let max_size = (sample_rate as f64 * MAX_RECORDING_SECONDS) as usize;
+
+    let mut pre_roll = VecDeque::new();
+    let mut clip: Vec<f32> = Vec::new();
+    let mut recording = false;
+    let mut silence = 0;
+    loop {
+        let chunk = agc.process(mic_receiver.recv().map_err(|err| err.to_string())?);
+        if chunk.is_empty() {
+            continue;
+        }
+        let rms = (chunk.iter().map(|&s| s * s).sum::<f32>() / chunk.len() as f32).sqrt();
+        let loud = rms >= START_RMS;
+
+        if !recording {
+            pre_roll.extend(chunk);
+            while pre_roll.len() > pre_roll_size {
+                pre_roll.pop_front();
+            }
+            if loud {
+                recording = true;
+                clip.extend(pre_roll.drain(..));
+            }
+            continue;
+        }
+
+        silence = if loud { 0 } else { silence + chunk.len() };
+        clip.extend(chunk);
+        if silence >= end_silence_size || clip.len() >= max_size {
+            // Trim off most of the trailing silence
+            let keep = clip.len() - silence.saturating_sub(pre_roll_size).min(clip.len());
+            clip.truncate(keep);
+            return Ok(clip);
+        }
+    }
+}
+
+/// Finds a `wake_N.wav` in the folder that doesn't exist yet
+fn next_free_path(dir: &Path) -> PathBuf {
+    (1..)
+        .map(|n| dir.join(format!("wake_{}.wav", n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Write mono audio to a 16-bit WAV file
+fn save_wav(path: &Path, samples: &[f32], sample_rate: u32) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(|err| err.to_string())?;
+    for &sample in samples {
+        writer
+            .write_sample((sample * i16::MAX as f32) as i16)
+            .map_err(|err| err.to_string())?;
+    }
+    writer.finalize().map_err(|err| err.to_string())
+}
@@ -0,0 +1,346 @@
+//! What DeepSpeech thinks was said, lined up into columns of alternatives.
+//! Words get lined up by when they were said, not by how many words came before them,
+//! so one transcript hearing an extra word doesn't throw off all the others.
+
+use deepspeech::CandidateTranscript;
+
+use std::ops::Range;
+
+/// DeepSpeech's confidences get divided by this before turning them into weights.
+/// Bigger means the less likely transcripts get more of a say.
+const CONFIDENCE_TEMPERATURE: f64 = 5.0;
+/// How long (in seconds) each of DeepSpeech's timesteps is.
+/// Tokens only say when they start, so this is how long we say the last letter of a word lasts.
+const TIMESTEP_SECONDS: f32 = 0.02;
+/// Columns that less than this much of the transcripts heard anything in
+/// (and the best one didn't either) get left out of the arguments.
+const MIN_ARGUMENT_SUPPORT: f64 = 0.5;
+
+/// One of DeepSpeech's guesses at what was said
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub words: Vec<String>,
+    /// When each word starts and ends, in seconds
+    pub times: Vec<(f32, f32)>,
+    /// How sure DeepSpeech is about this one. Bigger is better; it's not a probability.
+    pub confidence: f64,
+}
+
+impl Transcript {
+    /// Glue DeepSpeech's letters back together into words, remembering when each was said.
+    pub fn from_deepspeech(tc: &CandidateTranscript) -> Result<Self, String> {
+        let mut words = Vec::new();
+        let mut times = Vec::new();
+        let mut word = String::new();
+        let mut start = 0.0;
+        let mut end = 0.0;
+        for token in tc.tokens() {
+            let text = token.text().map_err(|err| err.to_string())?;
+            if text.trim().is_empty() {
+                // Space between words
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                    times.push((start, end));
+                }
+                continue;
+            }
+            if word.is_empty() {
+                start = token.start_time();
+            }
+            word.push_str(text);
+            end = token.start_time() + TIMESTEP_SECONDS;
+        }
+        if !word.is_empty() {
+            words.push(word);
+            times.push((start, end));
+        }
+        Ok(Self {
+            words,
+            times,
+            confidence: tc.confidence(),
+        })
+    }
+
+    /// Just the words from `start` on
+    pub fn skip(&self, start: usize) -> Self {
+        Self {
+            words: self.words.iter().skip(start).cloned().collect(),
+            times: self.times.iter().skip(start).cloned().collect(),
+            confidence: self.confidence,
+        }
+    }
+}
+
+/// All the transcripts of an utterance, with their words lined up by time.
+#[derive(Debug, Clone, Default)]
+pub struct Lattice {
+    /// `columns[depth][certainty]` is what the `certainty`th most likely transcript heard
+    /// during the `depth`th stretch of audio. Each slot holds at most one word.
+    pub columns: Vec<Vec<Option<String>>>,
+    /// How much we believe each transcript, from most to least likely. These add up to 1.
+    pub weights: Vec<f64>,
+}
+
+impl Lattice {
+    /// Line up the words of each transcript into columns.
+    /// The transcripts should be in order from most to least likely.
+    pub fn new(transcripts: &[Transcript]) -> Self {
+        let mut columns: Vec<Vec<Option<String>>> = Vec::new();
+        let mut times: Vec<(f32, f32)> = Vec::new();
+
+        for (certainty_idx, transcript) in transcripts.iter().enumerate() {
+            // Words can't go back in time, so each one goes in a later column than the last one.
+            // If two words crowd into one stretch, the second one splits off a column of its own.
+            let mut earliest = 0;
+            for (word, &(start, end)) in transcript.words.iter().zip(transcript.times.iter()) {
+                // Find the column this word overlaps the most
+                let best = times
+                    .iter()
+                    .enumerate()
+                    .skip(earliest)
+                    .map(|(idx, &(col_start, col_end))| {
+                        (idx, end.min(col_end) - start.max(col_start))
+                    })
+                    .filter(|&(_, overlap)| overlap > 0.0)
+                    .fold(
+                        None,
+                        |best: Option<(usize, f32)>, (idx, overlap)| match best {
+                            Some((_, best_overlap)) if best_overlap >= overlap => best,
+                            _ => Some((idx, overlap)),
+                        },
+                    );
+
+                let column_idx = match best {
+                    Some((idx, _)) => idx,
+                    None => {
+                        // No free column lines up with this word, so it gets one of its own
+                        let idx = times
+                            .iter()
+                            .skip(earliest)
+                            .position(|&(col_start, _)| col_start > start)
+                            .map_or(times.len(), |pos| pos + earliest);
+                        times.insert(idx, (start, end));
+                        columns.insert(idx, vec![None; transcripts.len()]);
+                        idx
+                    }
+                };
+
+                columns[column_idx][certainty_idx] = Some(word.clone());
+                earliest = column_idx + 1;
+            }
+        }
+
+        Self {
+            columns,
+            weights: weights(transcripts),
+        }
+    }
+
+    /// Just the columns in `range`, like for one command out of several said in a row
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        Self {
+            columns: self.columns[range].to_vec(),
+            weights: self.weights.clone(),
+        }
+    }
+
+    /// The word most of the transcripts agree on in each column, if they agree on hearing anything
+    pub fn likely_words(&self) -> Vec<Option<String>> {
+        self.columns
+            .iter()
+            .map(|possibilities| {
+                let mut votes: Vec<(String, f64)> = Vec::new();
+                for (word, &weight) in possibilities.iter().zip(self.weights.iter()) {
+                    if let Some(word) = word {
+                        let word = word.to_lowercase();
+                        match votes.iter_mut().find(|(w, _)| *w == word) {
+                            Some((_, total)) => *total += weight,
+                            None => votes.push((word, weight)),
+                        }
+                    }
+                }
+                let heard: f64 = votes.iter().map(|(_, weight)| weight).sum();
+                if heard < MIN_ARGUMENT_SUPPORT {
+                    return None;
+                }
+                votes
+                    .into_iter()
+                    .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+                    .map(|(word, _)| word)
+            })
+            .collect()
+    }
+
+    /// The alternatives for each column from `start` on, most likely first.
+    /// Columns where only a few unlikely transcripts heard something are left out,
+    /// so stray words don't shift the arguments around.
+    pub fn alternatives(&self, start: usize) -> Vec<Vec<String>> {
+        self.columns
+            .iter()
+            .skip(start)
+            .filter(|possibilities| {
+                let support: f64 = possibilities
+                    .iter()
+                    .zip(self.weights.iter())
+                    .filter(|(word, _)| word.is_some())
+                    .map(|(_, &weight)| weight)
+                    .sum();
+                possibilities.first().is_some_and(Option::is_some)
+                    || support >= MIN_ARGUMENT_SUPPORT
+            })
+            .map(|possibilities| {
+                possibilities
+                    .iter()
+                    .filter_map(|it| it.clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// Turn DeepSpeech's confidences into weights that add up to 1
+pub fn weights(transcripts: &[Transcript]) -> Vec<f64> {
+    let best = transcripts
+        .iter()
+        .map(|tc| tc.confidence)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let raw = transcripts
+        .iter()
+        .map(|tc| ((tc.confidence - best) / CONFIDENCE_TEMPERATURE).exp())
+        .collect::<Vec<_>>();
+    let total: f64 = raw.iter().sum();
+    raw.into_iter().map(|w| w / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A transcript with each word taking up half a second
+    fn transcript(text: &str, confidence: f64) -> Transcript {
+        let words = text
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let times = (0..words.len())
+            .map(|idx| (idx as f32 * 0.5, idx as f32 * 0.5 + 0.4))
+            .collect();
+        Transcript {
+            words,
+            times,
+            confidence,
+        }
+    }
+
+    fn timed(words: &[(&str, f32, f32)], confidence: f64) -> Transcript {
+        Transcript {
+            words: words.iter().map(|&(word, _, _)| word.to_string()).collect(),
+            times: words.iter().map(|&(_, start, end)| (start, end)).collect(),
+            confidence,
+        }
+    }
+
+    #[test]
+    fn lines_up_words() {
+        let lattice = Lattice::new(&[
+            transcript("what time is it", -1.0),
+            transcript("what time", -2.0),
+        ]);
+        assert_eq!(lattice.columns.len(), 4);
+        assert_eq!(
+            lattice.columns[1],
+            vec![Some("time".into()), Some("time".into())]
+        );
+        assert_eq!(lattice.columns[3], vec![Some("it".into()), None]);
+        assert_eq!(
+            lattice.alternatives(2),
+            vec![vec!["is".to_string()], vec!["it".to_string()]]
+        );
+    }
+
+    #[test]
+    fn an_extra_word_gets_its_own_column() {
+        let lattice = Lattice::new(&[
+            timed(&[("what", 0.0, 0.3), ("time", 0.6, 0.9)], -1.0),
+            timed(
+                &[("what", 0.0, 0.3), ("the", 0.35, 0.55), ("time", 0.6, 0.9)],
+                -3.0,
+            ),
+        ]);
+        assert_eq!(
+            lattice.columns,
+            vec![
+                vec![Some("what".to_string()), Some("what".to_string())],
+                vec![None, Some("the".to_string())],
+                vec![Some("time".to_string()), Some("time".to_string())],
+            ]
+        );
+        // Only the unlikely transcript heard "the", so it's not an argument
+        assert_eq!(
+            lattice.alternatives(0),
+            vec![
+                vec!["what".to_string(), "what".to_string()],
+                vec!["time".to_string(), "time".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn words_said_close_together_stay_apart() {
+        let lattice = Lattice::new(&[
+            timed(
+                &[("roll", 0.0, 0.3), ("two", 0.4, 0.7), ("d", 0.8, 1.0)],
+                -1.0,
+            ),
+            timed(
+                &[("role", 0.0, 0.3), ("to", 0.4, 0.55), ("dee", 0.6, 0.7)],
+                -2.0,
+            ),
+        ]);
+        // "to" and "dee" both fit under "two", but they're still two words
+        let word = |w: &str| Some(w.to_string());
+        assert_eq!(
+            lattice.columns,
+            vec![
+                vec![word("roll"), word("role")],
+                vec![word("two"), word("to")],
+                vec![None, word("dee")],
+                vec![word("d"), None],
+            ]
+        );
+    }
+
+    #[test]
+    fn weights_favor_the_confident() {
+        let lattice = Lattice::new(&[
+            transcript("time", -10.0),
+            transcript("tim", -12.0),
+            transcript("thyme", -30.0),
+        ]);
+        let total: f64 = lattice.weights.iter().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(lattice.weights[0] > lattice.weights[1]);
+        assert!(lattice.weights[1] > lattice.weights[2]);
+    }
+
+    #[test]
+    fn most_likely_words_win_the_vote() {
+        let lattice = Lattice::new(&[
+            transcript("roll two dee six", -1.0),
+            transcript("role to d six", -1.5),
+            transcript("roll to d sex", -1.6),
+        ]);
+        assert_eq!(
+            lattice.likely_words(),
+            vec![
+                Some("roll".to_string()),
+                Some("to".to_string()),
+                Some("d".to_string()),
+                Some("six".to_string()),
+            ]
+        );
+        let rest = lattice.slice(1..3);
+        assert_eq!(rest.columns.len(), 2);
+        assert_eq!(rest.weights, lattice.weights);
+    }
+}
@@ -1,8 +1,48 @@
 mod audio;
+mod commands;
+mod config;
+mod enroll;
+mod lattice;
 mod mega_state;
+mod parsing;
+mod phrases;
+mod sandbox;
+mod script;
+mod store;
 use mega_state::MegaState;
 
 fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("enroll") => {
+            println!("Enrolling wake word samples...");
+            if let Err(err) = enroll::run() {
+                println!("Enrollment failed! {}", err);
+            }
+            return;
+        }
+        Some("list") => {
+            match commands::index::Folder::scan(commands::COMMANDS_ROOT) {
+                Ok((root, _)) => print!("{}", root),
+                Err(err) => println!("Could not list the commands! {}", err),
+            }
+            return;
+        }
+        Some("lint") => {
+            match commands::lint::lint(commands::COMMANDS_ROOT) {
+                Ok(diagnostics) if diagnostics.is_empty() => println!("No problems found!"),
+                Ok(diagnostics) => {
+                    for diagnostic in diagnostics.iter() {
+                        println!("{}", diagnostic);
+                    }
+                    println!("Found {} problems", diagnostics.len());
+                }
+                Err(err) => println!("Could not check the commands! {}", err),
+            }
+            return;
+        }
+        _ => {}
+    }
+
     println!("Initializing Mega...");
     let mut mega = MegaState::new();
     println!("Starting Mega...");
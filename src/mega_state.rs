@@ -1,4 +1,12 @@
 use crate::audio;
+use crate::commands::{self, Candidate, CommandIndex, Resolution};
+use crate::config::{self, Config, FeedbackStyle, ListenConfig, WakeConfig};
+use crate::lattice::{self, Lattice, Transcript};
+use crate::parsing::slots::Filled;
+use crate::phrases;
+use crate::sandbox::Sandbox;
+use crate::script::{Reply, Request, Script};
+use crate::store::Store;
 use crate::DEEPSPEECH_SAMPLE_RATE;
 
 use audrey::read::Reader;
@@ -10,9 +18,9 @@ use audrey::sample::{
     Sample,
 };
 use deepspeech::{Metadata, Model};
-use rlua::{Lua, Error as LuaError, Table};
 use crossbeam::channel;
 
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::time;
@@ -20,8 +28,12 @@ use std::{
     collections::{HashMap, VecDeque},
     thread,
 };
-use std::fs;
-use audio::speech_synthesis;
+use audio::{
+    agc::Agc,
+    earcon::{Earcon, Earcons},
+    keyword_spotter::KeywordSpotter,
+    speech_synthesis::{self, Voice},
+};
 
 /// How loud you have to be for Mega to count you as speaking
 const ACTIVATION_THRESHOLD: f64 = 0.01;
@@ -34,35 +46,79 @@ const THRESHOLD_TIME_SECONDS: f64 = 1.0;
 /// The `lambda` parameter in `tvid::condat`
 const DENOISE_RADIUS: f32 = 0.05;
 
-/// The amount of time it buffers while listening for "Mega"
+/// The amount of time it buffers while listening for the wake phrase
 const ACTIVATION_BUFFER_SIZE_SECONDS: f64 = 2.0;
+/// The amount of time it buffers once the keyword spotter hears the wake phrase,
+/// so there's room for a command said right after it, like "mega roll two dee six"
+const SPOTTED_BUFFER_SIZE_SECONDS: f64 = 8.0;
 /// The amount of time it buffers while listening for a command
 const COMMAND_BUFFER_SIZE_SECONDS: f64 = 15.0;
+/// The mic lags a little behind the speakers, so after Mega finishes asking a question
+/// it waits this long before listening for the answer, so it doesn't hear the end of itself.
+const ECHO_SECONDS: f64 = 0.3;
+/// If Mega never hears back that it's done asking a question, it stops waiting and listens anyway
+/// after about this long per letter of the question...
+const MAX_SPEAKING_SECONDS_PER_CHAR: f64 = 0.15;
+/// ... plus this long, for anything it was still saying before it
+const MAX_SPEAKING_EXTRA_SECONDS: f64 = 5.0;
+/// Ways to pick the first, second or third of some choices
+const ORDINALS: [&[&str]; 3] = [
+    &["first", "one", "1st"],
+    &["second", "two", "2nd"],
+    &["third", "three", "3rd"],
+];
 
 /// MegaState handles the state of the Mega instance.
 #[allow(dead_code)]
 pub struct MegaState {
+    /// Settings from `mega.toml`
+    config: Config,
+
     speaker_sender: mpsc::Sender<Vec<f32>>,
     speaker_thread_handle: thread::JoinHandle<()>,
     speaker_sample_rate: u32,
     mic_receiver: mpsc::Receiver<Vec<f32>>,
     mic_thread_handle: thread::JoinHandle<()>,
     mic_sample_rate: u32,
+    /// Evens out the microphone level
+    agc: Agc,
+    /// Listens for the wake word cheaply, if the user has enrolled any recordings
+    spotter: Option<KeywordSpotter>,
 
     /// Speech to text
     speech_model: Model,
 
+    /// Beeps and boops
+    earcons: Earcons,
+
+    /// All the commands we know about
+    commands: CommandIndex,
+
     /// Text to speech
-    synther_sender: channel::Sender<String>,
+    voice: Voice,
+    /// Gets the ids of the things `voice` has finished saying
+    spoken_receiver: channel::Receiver<u64>,
     synther_thread_handle: thread::JoinHandle<()>,
 
     /// State machine
     state: State,
+    /// Commands still to run from the last thing they said, like the "weather" in "time and weather"
+    queue: VecDeque<Lattice>,
+    /// Commands that are waiting (like with `Mega.sleep`), or that have finished
+    /// but still have timers or other callbacks waiting
+    background: Vec<Script>,
+    /// What Mega's finished saying since the background commands were last told
+    background_spoken: Vec<u64>,
+    /// How many times in a row each command has gone wrong
+    failures: HashMap<PathBuf, u32>,
 }
 
 impl MegaState {
     /// Return a new MegaState ready for running
     pub fn new() -> Self {
+        // Load settings
+        let config = Config::load(config::CONFIG_PATH).expect("Could not load the config file!");
+
         // Spin up the audio
         let (
             (speaker_sender, speaker_thread_handle, speaker_sample_rate),
@@ -92,7 +148,33 @@ impl MegaState {
         }
 
         // Init speech synthesizer
-        let (synther_sender, synther_thread_handle) = speech_synthesis::init().unwrap();
+        let (voice, spoken_receiver, synther_thread_handle) = speech_synthesis::init().unwrap();
+
+        // Init earcons
+        let earcons = Earcons::load(&config.feedback.sounds, speaker_sample_rate)
+            .expect("Could not load the earcons!");
+
+        // Index the commands
+        let commands =
+            CommandIndex::new(commands::COMMANDS_ROOT).expect("Could not index the commands!");
+
+        // Init AGC
+        let agc = Agc::new(mic_sample_rate);
+
+        // Init the keyword spotter
+        let spotter = if config.spotter.enabled {
+            KeywordSpotter::load(
+                &config.spotter.templates,
+                mic_sample_rate,
+                config.spotter.threshold_scale,
+            )
+            .expect("Could not load the wake word recordings!")
+        } else {
+            None
+        };
+        if spotter.is_none() {
+            println!("No keyword spotter; DeepSpeech will listen for the wake word");
+        }
 
         // Init state
         let state = State::new_idle(speaker_sample_rate as f64);
@@ -100,16 +182,26 @@ impl MegaState {
         println!("Mega initialized!");
 
         Self {
+            config,
             speaker_sender,
             speaker_thread_handle,
             speaker_sample_rate,
             mic_receiver,
             mic_thread_handle,
             mic_sample_rate,
+            agc,
+            spotter,
             speech_model,
-            synther_sender,
+            earcons,
+            commands,
+            voice,
+            spoken_receiver,
             synther_thread_handle,
             state,
+            queue: VecDeque::new(),
+            background: Vec::new(),
+            background_spoken: Vec::new(),
+            failures: HashMap::new(),
         }
     }
 
@@ -117,18 +209,98 @@ impl MegaState {
     /// This will block forever until something horrible happens.
     pub fn start(&mut self) -> Result<(), String> {
         'main: loop {
+            // Pick up any changes to the commands
+            let changed = self.commands.refresh();
+            if !changed.is_empty() && !self.failures.is_empty() {
+                // Maybe they fixed whatever was wrong
+                self.failures
+                    .retain(|path, _| !changed.iter().any(|changed| path.starts_with(changed)));
+            }
+
+            // What Mega's finished saying since last time
+            let spoken = self.spoken_receiver.try_iter().collect::<Vec<_>>();
+
+            // Run the callbacks for commands in the background,
+            // but not while Mega is listening to someone, so they don't talk over them.
+            // Anything that comes due in the meantime goes off once Mega's done listening.
+            self.background_spoken.extend(spoken.iter().cloned());
+            if self.state.lets_background_run() {
+                let spoken = mem::take(&mut self.background_spoken);
+                let mut background = Vec::with_capacity(self.background.len());
+                for mut script in mem::take(&mut self.background) {
+                    let pending = script
+                        .catch_up(&spoken)
+                        .and_then(|_| script.has_callbacks());
+                    match pending {
+                        Ok(pending) if pending || !script.is_finished() => background.push(script),
+                        Ok(_) => println!("{:?} has no callbacks left", script.path),
+                        Err(err) => self.command_failed(&script.path, &err)?,
+                    }
+                }
+                self.background = background;
+
+                // A command that was waiting can carry on, once nothing else is going on
+                if let State::Idle { .. } = self.state {
+                    if let Some(idx) = self.background.iter().position(Script::is_ready) {
+                        let script = self.background.remove(idx);
+                        println!("{:?} is done waiting", script.path);
+                        self.state = State::ExecingCommand {
+                            script,
+                            reply: Some(Reply::Continue),
+                        };
+                        continue 'main;
+                    }
+                }
+            }
+
+            // Complain about the mic level if it's been bad for a while
+            if let Some(warning) = self.agc.take_warning() {
+                println!("{}", warning);
+                self.speak(warning.hint())?;
+            }
+
             match self.state {
                 State::Idle {
                     ref mut audio_buffer,
                     buf_size,
                     loudness_check_size,
                     ref mut crossed_loudness,
+                    ref mut spotted_for,
+                    ref mut spotted_audio,
+                    spotted_buf_size,
                 } => {
                     // Get the next audio bits from the microphone
-                    let new_audio = self.mic_receiver.try_iter();
+                    let agc = &mut self.agc;
+                    let new_audio = self
+                        .mic_receiver
+                        .try_iter()
+                        .map(|chunk| agc.process(chunk))
+                        .collect::<Vec<_>>();
+
+                    // Let the keyword spotter have a listen
+                    if let Some(spotter) = &mut self.spotter {
+                        for (idx, chunk) in new_audio.iter().enumerate() {
+                            if let Some(samples) = spotted_for {
+                                *samples += chunk.len();
+                                buffer_audio(
+                                    spotted_audio,
+                                    std::iter::once(chunk.clone()),
+                                    spotted_buf_size,
+                                );
+                            }
+                            if let Some(cost) = spotter.push(chunk) {
+                                println!("Keyword spotter heard the wake word (cost {:.2})", cost);
+                                *spotted_for = Some(0);
+                                // Keep the wake word itself too, so DeepSpeech can tell where it ends
+                                spotted_audio.clear();
+                                spotted_audio.extend(audio_buffer.iter());
+                                spotted_audio.extend(new_audio[..=idx].iter().flatten());
+                            }
+                        }
+                    }
 
                     // Buffer in the new audio
-                    buffer_audio(audio_buffer, new_audio, buf_size);
+                    buffer_audio(audio_buffer, new_audio.into_iter(), buf_size);
 
                     // See if it's LOUD ENOUGH to warrant trying to scan for words
                     let loudness: f64 = audio_buffer
@@ -143,249 +315,634 @@ impl MegaState {
                         // OK, it's worth listening!
                         *crossed_loudness = true;
                     } else if *crossed_loudness && avg_loudness < ACTIVATION_THRESHOLD {
-                        // We're done speaking; let's-a go!
+                        // We're done speaking
                         *crossed_loudness = false;
-                        print!("Processing... ");
-                        flush();
-
-                        let (speech, dur) = MegaState::text_to_speech(
-                            &mut self.speech_model,
-                            audio_buffer.iter().cloned(),
-                            self.mic_sample_rate,
-                        )?;
-                        print!(
-                            "in {:.2} seconds ({:.0}% of RT): ",
-                            dur.as_secs_f64(),
-                            100.0 * dur.as_secs_f64() / ACTIVATION_BUFFER_SIZE_SECONDS
-                        );
-                        let found_mega = speech.transcripts().iter().any(|tc| {
-                            // Confusingly, tc.tokens() yields the separate letters.
-                            // Perhaps in other natlangs they mean something different?
-                            print!("{}, ", tc);
-                            tc.to_string() == "mega"
-                        });
-                        if found_mega {
-                            print!("Found \"mega\"!");
-                            self.speak("ready")?;
-                            self.state = State::new_heard_trigger(self.mic_sample_rate as f64);
+
+                        if self.spotter.is_none() {
+                            // No spotter, so it's up to DeepSpeech; let's-a go!
+                            print!("Processing (gain {:.1}x)... ", self.agc.gain());
+                            flush();
+                            let found = MegaState::listen_for_wake_phrase(
+                                &mut self.speech_model,
+                                audio_buffer.iter().cloned(),
+                                self.mic_sample_rate,
+                                &self.config.wake,
+                            )?;
+                            if let Some(rests) = found {
+                                self.wake_up(rests)?;
+                            }
+                            continue 'main;
+                        }
+                    }
+
+                    if let Some(samples) = *spotted_for {
+                        if !*crossed_loudness {
+                            // The spotter heard the wake word, and they've stopped talking.
+                            if let Some(spotter) = &mut self.spotter {
+                                spotter.reset();
+                            }
+                            if samples <= loudness_check_size * 3 / 2 {
+                                self.wake_up(Vec::new())?;
+                                continue 'main;
+                            }
+                            // They kept going for a while after it, so they probably said a command too.
+                            // Hand the whole thing to DeepSpeech.
+                            let spotted_audio = std::mem::take(spotted_audio);
+                            print!("Processing (gain {:.1}x)... ", self.agc.gain());
+                            flush();
+                            let found = MegaState::listen_for_wake_phrase(
+                                &mut self.speech_model,
+                                spotted_audio.iter().cloned(),
+                                self.mic_sample_rate,
+                                &self.config.wake,
+                            )?;
+                            match found {
+                                Some(rests) => self.wake_up(rests)?,
+                                None => {
+                                    // The spotter's pretty sure it heard the wake word even if DeepSpeech isn't,
+                                    // so everything after it must be the command
+                                    println!("Taking what came after the wake word as the command");
+                                    let command = spotted_audio
+                                        .iter()
+                                        .skip(spotted_audio.len().saturating_sub(samples))
+                                        .cloned()
+                                        .collect();
+                                    self.heard_command(command)?;
+                                }
+                            }
                         }
-                        println!("");
                     }
                 }
-                State::HeardTrigger {
-                    ref mut audio_buffer,
-                    buf_size,
-                    loudness_check_size,
-                    ref mut crossed_loudness,
-                } => {
+                State::HeardTrigger { ref mut listener } => {
                     // Get the next audio bits from the microphone
-                    let new_audio = self.mic_receiver.try_iter();
-
-                    // Buffer in the new audio
-                    buffer_audio(audio_buffer, new_audio, buf_size);
+                    let agc = &mut self.agc;
+                    let new_audio = self
+                        .mic_receiver
+                        .try_iter()
+                        .map(|chunk| agc.process(chunk))
+                        .collect::<Vec<_>>();
 
-                    // See if it's LOUD ENOUGH to warrant trying to scan for words
-                    let loudness: f64 = audio_buffer
-                        .iter()
-                        .rev()
-                        .take(loudness_check_size)
-                        .fold(0.0, |acc, &sample| acc + sample.abs() as f64);
-                    let avg_loudness = loudness / loudness_check_size as f64;
-                    // println!("Loudness: {}", avg_loudness);
+                    match listener.listen(new_audio)? {
+                        Heard::Nothing => {}
+                        Heard::Silence => {
+                            println!("Nobody said anything; going back to sleep");
+                            self.dismiss()?;
+                        }
+                        Heard::Speech(audio) => self.heard_command(audio)?,
+                    }
+                }
+                State::Dialog {
+                    ref mut listener,
+                    ref mut purpose,
+                } => {
+                    for &id in spoken.iter() {
+                        listener.spoken(id);
+                    }
+                    if let DialogPurpose::Script { script, .. } = purpose {
+                        if let Err(err) = spoken.iter().try_for_each(|&id| script.spoken(id)) {
+                            let path = script.path.clone();
+                            self.command_failed(&path, &err)?;
+                            self.go_idle();
+                            continue 'main;
+                        }
+                    }
+                    // Timers wait until the question's been answered, so they don't talk over it
+                    // (and so do everyone else's; see above)
+                    let agc = &mut self.agc;
+                    let new_audio = self
+                        .mic_receiver
+                        .try_iter()
+                        .map(|chunk| agc.process(chunk))
+                        .collect::<Vec<_>>();
 
-                    if !*crossed_loudness && avg_loudness >= ACTIVATION_THRESHOLD {
-                        // OK, it's worth listening!
-                        *crossed_loudness = true;
-                    } else if *crossed_loudness && avg_loudness < ACTIVATION_THRESHOLD {
-                        // We're done speaking; let's-a go!
-                        *crossed_loudness = false;
-                        print!("Processing command... ");
-                        flush();
-
-                        // Send only the parts starting when it goes above the threshold to DeepSpeech
-                        let mut running_loudness = 0.0;
-                        let start_index =
-                            audio_buffer
-                                .iter()
-                                .enumerate()
-                                .find_map(|(idx, &sample)| {
-                                    // Compute the running average
-                                    // Add the newest divided by the length,
-                                    // subtract the oldest divided by the length.
-                                    running_loudness += (sample.abs()
-                                        - match idx.checked_sub(loudness_check_size) {
-                                            Some(idx) => audio_buffer[idx], 
-                                            None => 0.0
-                                        }.abs())
-                                        / loudness_check_size as f32;
-                                    // Check if we're loud enough
-                                    if running_loudness >= ACTIVATION_THRESHOLD as f32 {
-                                        Some(idx.saturating_sub(loudness_check_size / 2))
-                                    } else {
-                                        None
-                                    }
-                                }).ok_or_else(|| "Somehow tried to process a command both loud enough and not loud enough")?;
-
-                        print!("Starting at index {}... ", start_index);
-                        flush();
-                        // self.speaker_sender.send(audio_buffer.iter().skip(start_index).cloned().collect()).map_err(|err| err.to_string())?;
-
-                        let (speech, dur) = MegaState::text_to_speech(
-                            &mut self.speech_model,
-                            audio_buffer.iter().skip(start_index).cloned(),
-                            self.mic_sample_rate,
-                        )?;
-                        println!(
-                            "in {:.2} seconds ({:.0}% of RT):",
-                            dur.as_secs_f64(),
-                            100.0 * dur.as_secs_f64()
-                                / ((audio_buffer.len() - start_index)
-                                    / self.mic_sample_rate as usize)
-                                    as f64
-                        );
-
-                        // First process into a HashMap indexed by (depth, certainty)
-                        let mut tree_map: HashMap<(usize, usize), String> = HashMap::new();
-                        let mut max_certainty = 0;
-                        let mut max_depth = 0;
-                        for (certainty_idx, tc) in speech.transcripts().iter().enumerate() {
-                            max_certainty = max_certainty.max(certainty_idx);
-                            // This goes depth-first, but we want breadth-first.
-                            let sentence = tc.to_string();
-                            let split = sentence.split_whitespace();
-                            for (depth_idx, word) in split.enumerate() {
-                                max_depth = max_depth.max(depth_idx);
-                                tree_map.insert((depth_idx, certainty_idx), word.to_string());
+                    match listener.listen(new_audio)? {
+                        Heard::Nothing => {}
+                        Heard::Silence => {
+                            if let DialogPurpose::Script { .. } = purpose {
+                                // Let the command decide what to do about it
+                                println!("No answer; telling the command");
+                                self.reply_to_script(None);
+                            } else {
+                                println!("No answer; going back to sleep");
+                                self.dismiss()?;
                             }
                         }
-                        // println!("{:?}", &tree_map);
-
-                        // Flatten into a Vec<Vec<String>>
-                        // First level is all the possibilites for this depth in the tree.
-                        let mut tree: Vec<Vec<Option<String>>> =
-                            (0..=max_depth).map(|_| vec![None; max_certainty]).collect();
-                        for certainty_idx in 0..max_certainty {
-                            for depth_idx in 0..=max_depth {
-                                let word = tree_map.remove(&(depth_idx, certainty_idx));
-                                tree[depth_idx][certainty_idx] = word;
+                        Heard::Speech(audio) => {
+                            print!("Processing answer (gain {:.1}x)... ", self.agc.gain());
+                            flush();
+                            let transcripts = MegaState::transcribe(
+                                &mut self.speech_model,
+                                audio,
+                                self.mic_sample_rate,
+                            )?;
+                            if let Some(best) = transcripts.first() {
+                                if is_cancel(&best.words, &self.config.listen) {
+                                    println!("Cancelled!");
+                                    self.dismiss()?;
+                                    continue 'main;
+                                }
                             }
-                        }
-                        self.speak("searching for command")?;
 
-                        // To the bat-command!
-                        self.state = State::new_searching_for_command(tree);
+                            match purpose {
+                                DialogPurpose::Disambiguate { command, choices } => {
+                                    let spoken =
+                                        choices.iter().map(Candidate::spoken).collect::<Vec<_>>();
+                                    match pick_choice(
+                                        &transcripts,
+                                        &spoken,
+                                        self.config.commands.name_sensitivity,
+                                    ) {
+                                        Some(idx) => {
+                                            let choice = choices[idx].clone();
+                                            let args = command.alternatives(choice.args_start);
+                                            println!("They meant {:?}", choice.path);
+                                            self.exec(choice, args)?;
+                                        }
+                                        None => {
+                                            println!("Couldn't tell which one they picked");
+                                            self.feedback(Earcon::NotFound)?;
+                                            self.go_idle();
+                                        }
+                                    }
+                                }
+                                DialogPurpose::FillSlots { found, args, filled } => {
+                                    let mut filled = filled.clone();
+                                    let slot = filled.missing.remove(0);
+                                    let answer = Lattice::new(&transcripts).alternatives(0);
+                                    match slot.parse(&answer) {
+                                        Some(value) => {
+                                            println!("Filled {} with {}", slot.name, value);
+                                            filled.values.insert(slot.name, value);
+                                            let (found, args) = (found.clone(), args.clone());
+                                            self.fill_slots(found, args, filled)?;
+                                        }
+                                        None => {
+                                            println!("Couldn't get {} out of that", slot.name);
+                                            self.feedback(Earcon::NotFound)?;
+                                            self.go_idle();
+                                        }
+                                    }
+                                }
+                                DialogPurpose::Script { .. } => {
+                                    self.reply_to_script(Some(&transcripts));
+                                }
+                            }
+                        }
                     }
                 }
                 State::SearchingForCommand { ref command } => {
-                    // The path that we know is all OK.
-                    let mut path = PathBuf::from("commands");
-                    'level: for (idx, possibilities) in command.iter().enumerate() {
-                        for poss in possibilities {
-                            let try_path = path.join(poss.as_ref().unwrap_or(&"".to_string()));
-                            println!("Trying path {:?} ", try_path);
-                            if try_path.exists() {
-                                if try_path.is_dir() {
-                                    // Nice, a folder! Let's keep going
-                                    path = try_path;
-                                    continue 'level;
-                                } else {
-                                    // what are you doing?
-                                    let msg = format!("Invalid file found in commands: {:?}", try_path.file_name());
-                                    println!("{}", msg.clone());
-                                    self.speak(msg)?;
-                                    self.state = State::new_idle(self.mic_sample_rate as f64);
-                                    continue 'main;
-                                }
+                    // They might have said a few commands at once
+                    let mut segments = self
+                        .commands
+                        .root()
+                        .split(command, &self.config.commands);
+                    let command = if segments.is_empty() {
+                        command.clone()
+                    } else {
+                        segments.remove(0)
+                    };
+                    if !segments.is_empty() {
+                        println!("Heard {} commands in a row", segments.len() + 1);
+                        self.queue.extend(segments);
+                    }
+
+                    match self
+                        .commands
+                        .root()
+                        .resolve(&command, &self.config.commands)
+                    {
+                        Resolution::Found(found) => {
+                            // Fill the arguments
+                            let args = command.alternatives(found.args_start);
+                            self.exec(found, args)?;
+                        }
+                        Resolution::Ambiguous(choices) => {
+                            let spoken = choices.iter().map(Candidate::spoken).collect::<Vec<_>>();
+                            println!("Not sure which they meant: {:?}", spoken);
+                            let question =
+                                format!("Did you mean {}?", phrases::spoken_list(&spoken, "or"));
+                            self.ask(
+                                question,
+                                DialogPurpose::Disambiguate {
+                                    command: command.clone(),
+                                    choices,
+                                },
+                            )?;
+                        }
+                        Resolution::Incomplete { candidate, options } => {
+                            println!(
+                                "Got to {:?} (score {:.3}), but it has no default command",
+                                candidate.path, candidate.score
+                            );
+                            if options.is_empty() {
+                                self.speak("There's nothing in there.")?;
                             } else {
-                                // But perhaps it's the path to a lua file?
-                                let luaed_path = path.join(format!("{}.lua", poss.as_ref().unwrap_or(&"".to_string())));
-                                if let Ok(md) = fs::metadata(luaed_path.clone()) {
-                                    // Hey, there's a file here! Or an oddly named folder.
-                                    if md.is_file() {
-                                        // Awesome we found the command~!
-                                        // Fill the arguments
-                                        let args = command
-                                            .iter()
-                                            .skip(idx + 1)
-                                            .map(|possibilities| 
-                                                possibilities
-                                                .iter()
-                                                .filter_map(|it| it.clone())
-                                                .collect::<Vec<_>>()
-                                            )
-                                            .collect::<Vec<Vec<String>>>();
-
-                                        self.speak("Executing command")?;
+                                self.speak(format!(
+                                    "You can say {}.",
+                                    phrases::spoken_list(&options, "or")
+                                ))?;
+                            }
+                            self.go_idle();
+                        }
+                        Resolution::NotFound { best_guess } => {
+                            self.feedback(Earcon::NotFound)?;
+                            match best_guess {
+                                Some(guess) => println!(
+                                    "Failed to find the command; the closest was {:?} (score {:.3})",
+                                    guess.path, guess.score
+                                ),
+                                None => println!("Failed to find the command; nothing came close"),
+                            }
+                            self.go_idle();
+                        }
+                    }
+                }
+                State::ExecingCommand {
+                    ref mut script,
+                    ref mut reply,
+                } => {
+                    // Mega isn't listening while a command runs, so don't let the audio pile up
+                    self.mic_receiver.try_iter().for_each(drop);
+
+                    match script.pump(&spoken, reply.take()) {
+                        Ok(Some(request)) => {
+                            println!("{:?} asked {:?}", script.path, request.prompt());
+                            if let Some(script) = self.take_script() {
+                                let question = request.prompt().to_string();
+                                self.ask(question, DialogPurpose::Script { script, request })?;
+                            }
+                        }
+                        Ok(None) if script.is_finished() => {
+                            // It made it to the end, so whatever was wrong with it isn't anymore
+                            self.failures.remove(&script.path);
+                            if let Some(script) = self.take_script() {
+                                match script.has_callbacks() {
+                                    Ok(true) => {
                                         println!(
-                                            "Found command! {:?} with {:?}",
-                                            luaed_path.clone(),
-                                            args.clone()
+                                            "{:?} left some callbacks to run later",
+                                            script.path
                                         );
-                                        self.state = State::new_execing_command(
-                                            self.synther_sender.clone(), 
-                                            luaed_path, 
-                                            args
-                                        )?;
-
-                                        continue 'main;
-                                    } else {
-                                        // Else we have a *folder* named that.lua. Why would you do that?
+                                        self.background.push(script);
                                     }
-                                } else {
-                                    // Well, that attempt wasn't valid. Back to try another possibility.
+                                    Ok(false) => {}
+                                    Err(err) => self.command_failed(&script.path, &err)?,
                                 }
                             }
+                            self.next_command();
                         }
-                        // We ran out of paths ;(
-                        self.speak("Could not find that command.")?;
-                        println!("Failed to find the command after: {:?}", path);
-                        self.state = State::new_idle(self.mic_sample_rate as f64);
-                        continue 'main;
-                    }
-                    // Not sure how you get here, but i know it means you're out of possible commands
-                    self.speak("Could not find that command.")?;
-                    println!("Failed to find the command after: {:?}", path);
-                    self.state = State::new_idle(self.mic_sample_rate as f64);
-                    continue 'main;
-                }
-                State::ExecingCommand { ref path, ref args, ref lua_state } => {
-                    lua_state.context(|ctx| {
-                        let globals = ctx.globals();
-                        let mega_api = globals.get::<_, Table>("Mega")?;
-
-                        // Add the arguments to `Mega.arguments` and `Mega.raw_arguments`
-                        let mega_arguments = ctx.create_table()?;
-                        let mega_raw_arguments = ctx.create_table()?;
-                        for (arg_idx, possible_args) in args.iter().enumerate() {
-                            let mega_possibilities = ctx.create_table()?;
-                            for (possible_idx, possibility) in possible_args.iter().enumerate() {
-                                if possible_idx == 0 {
-                                    // add 1 to everything, because lua counts at 1...
-                                    mega_arguments.set(arg_idx + 1, possibility.clone())?;
-                                }
-                                mega_possibilities.set(possible_idx + 1, possibility.clone())?;
+                        Ok(None) => {
+                            // It's waiting for something, so let Mega get on with things meanwhile
+                            if let Some(script) = self.take_script() {
+                                println!(
+                                    "{:?} is waiting; moving it to the background",
+                                    script.path
+                                );
+                                self.background.push(script);
                             }
-                            mega_raw_arguments.set(arg_idx + 1, mega_possibilities)?;
+                            self.next_command();
+                        }
+                        Err(err) => {
+                            let path = script.path.clone();
+                            self.command_failed(&path, &err)?;
+                            self.go_idle();
                         }
-                        mega_api.set("arguments", mega_arguments)?;
-                        mega_api.set("raw_arguments", mega_raw_arguments)?;
+                    }
+                }
+            };
+        }
+    }
+
+    /// We heard the wake phrase!
+    /// `rests` has the words after the wake phrase in each transcript that had it.
+    /// If there's a command in there, go look for it; otherwise wait for one.
+    /// They said something after the wake phrase, so figure out what command it was
+    fn heard_command(&mut self, audio: Vec<f32>) -> Result<(), String> {
+        print!("Processing command (gain {:.1}x)... ", self.agc.gain());
+        flush();
+        let transcripts =
+            MegaState::transcribe(&mut self.speech_model, audio, self.mic_sample_rate)?;
+        if let Some(best) = transcripts.first() {
+            if is_cancel(&best.words, &self.config.listen) {
+                println!("Cancelled!");
+                return self.dismiss();
+            }
+        }
+        let lattice = Lattice::new(&transcripts);
+        self.feedback(Earcon::Searching)?;
+
+        // To the bat-command!
+        self.state = State::new_searching_for_command(lattice);
+        Ok(())
+    }
+
+    fn wake_up(&mut self, rests: Vec<Transcript>) -> Result<(), String> {
+        self.queue.clear();
+        match rests.first() {
+            Some(rest) if is_cancel(&rest.words, &self.config.listen) => {
+                println!("Cancelled!");
+                self.dismiss()?;
+            }
+            Some(rest) if !rest.words.is_empty() => {
+                println!("Heard a command along with the wake phrase: {:?}", rest.words);
+                self.feedback(Earcon::Searching)?;
+                self.state = State::new_searching_for_command(Lattice::new(&rests));
+            }
+            _ => {
+                self.feedback(Earcon::Ready)?;
+                self.state = State::new_heard_trigger(
+                    self.mic_sample_rate as f64,
+                    self.config.listen.timeout_seconds,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a command we found
+    fn exec(&mut self, mut found: Candidate, args: Vec<Vec<String>>) -> Result<(), String> {
+        // Things could have moved around since they were indexed
+        found.path = match self.commands.check(&found.path) {
+            Ok(path) => path,
+            Err(problem) => {
+                println!("Refusing to run {:?}: it {}", found.path, problem);
+                self.feedback(Earcon::Error)?;
+                self.go_idle();
+                return Ok(());
+            }
+        };
+        if self.is_turned_off(&found.path) {
+            println!(
+                "Not running {:?}: it's gone wrong too many times in a row",
+                found.path
+            );
+            self.feedback(Earcon::Error)?;
+            self.speak("That command is turned off until it's fixed.")?;
+            self.go_idle();
+            return Ok(());
+        }
+        let schema = self
+            .commands
+            .root()
+            .manifest_entry(&found.path)
+            .and_then(|entry| entry.slots.clone());
+        let filled = match schema {
+            Some(schema) => schema.fill(&args, self.config.commands.name_sensitivity),
+            None => Filled::default(),
+        };
+        self.fill_slots(found, args, filled)
+    }
+
+    /// Ask for the next slot the command needs, or run it if it has them all
+    fn fill_slots(
+        &mut self,
+        found: Candidate,
+        args: Vec<Vec<String>>,
+        filled: Filled,
+    ) -> Result<(), String> {
+        match filled.missing.first() {
+            Some(slot) => {
+                println!("Still need {} for {:?}", slot.name, found.path);
+                let question = slot.question();
+                self.ask(
+                    question,
+                    DialogPurpose::FillSlots {
+                        found,
+                        args,
+                        filled,
+                    },
+                )
+            }
+            None => {
+                self.feedback(Earcon::Executing)?;
+                println!(
+                    "Found command! {:?} (score {:.3}) with {:?}",
+                    found.path, found.score, args
+                );
+                if !filled.values.is_empty() {
+                    let slots = filled
+                        .values
+                        .iter()
+                        .map(|(name, value)| format!("{} = {}", name, value))
+                        .collect::<Vec<_>>();
+                    println!("Slots: {}", slots.join(", "));
+                }
+                let sandbox = Sandbox {
+                    allow: self
+                        .commands
+                        .root()
+                        .manifest_entry(&found.path)
+                        .map(|entry| entry.allow.clone())
+                        .unwrap_or_default(),
+                    limits: self.config.sandbox.clone(),
+                };
+                if !sandbox.allow.is_empty() {
+                    println!("{:?} is allowed {:?}", found.path, sandbox.allow);
+                }
+                let store = Store::for_command(
+                    &self.config.store.folder,
+                    self.commands
+                        .relative_path(&found.path)
+                        .unwrap_or(&found.path),
+                    self.config.store.max_bytes,
+                );
+                let path = found.path.clone();
+                let script = Script::new(
+                    self.voice.clone(),
+                    found.path,
+                    args,
+                    filled.values,
+                    found.score,
+                    store,
+                    sandbox,
+                );
+                match script {
+                    Ok(script) => {
+                        self.state = State::ExecingCommand {
+                            script,
+                            reply: Some(Reply::Start),
+                        };
+                    }
+                    Err(err) => {
+                        self.command_failed(&path, &err)?;
+                        self.go_idle();
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// A command went wrong. Say so, and turn it off if it keeps happening.
+    /// What Mega does next is up to whoever was running it.
+    fn command_failed(&mut self, path: &Path, err: &str) -> Result<(), String> {
+        println!("{:?} went wrong: {}", path, err);
+        let failures = self.failures.entry(path.to_path_buf()).or_insert(0);
+        *failures += 1;
+        let failures = *failures;
 
-                        // Load the command file and execute it
-                        let file = fs::read(path).unwrap(); // we know the file exists
-                        ctx.load(
-                            &file
-                        ).set_name("Mega api")?
-                        .exec()?;
+        self.feedback(Earcon::Error)?;
+        self.speak(format!("The {} command had a problem.", command_name(path)))?;
+        if self.is_turned_off(path) {
+            println!(
+                "{:?} has gone wrong {} times in a row; turning it off until it changes",
+                path, failures
+            );
+            self.speak("I've turned it off until it's fixed.")?;
+        }
+        Ok(())
+    }
 
-                        Ok(())
-                    }).map_err(|err: LuaError| err.to_string())?;
+    /// Whether a command has gone wrong too many times in a row to keep running it
+    fn is_turned_off(&self, path: &Path) -> bool {
+        let max = self.config.commands.max_failures;
+        max > 0 && self.failures.get(path).is_some_and(|&failures| failures >= max)
+    }
+
+    /// Say something and wait for an answer
+    fn ask(&mut self, question: String, purpose: DialogPurpose) -> Result<(), String> {
+        let id = self.speak(question.as_str())?;
+        let listener = Listener::new(
+            self.mic_sample_rate as f64,
+            self.config.listen.timeout_seconds,
+        )
+        .deaf_until_spoken(id, &question);
+        self.state = State::Dialog { listener, purpose };
+        Ok(())
+    }
 
-                    self.state = State::new_idle(self.mic_sample_rate as f64);
+    /// Hand a command what they said to its question (or None if they didn't say anything),
+    /// and let it carry on.
+    fn reply_to_script(&mut self, transcripts: Option<&[Transcript]>) {
+        let state = mem::replace(&mut self.state, State::new_idle(self.mic_sample_rate as f64));
+        if let State::Dialog {
+            purpose: DialogPurpose::Script { script, request },
+            ..
+        } = state
+        {
+            let reply = match request {
+                Request::Ask(_) => {
+                    Reply::Answer(transcripts.map(|tcs| Lattice::new(tcs).alternatives(0)))
                 }
+                Request::Confirm(_) => Reply::Confirmed(
+                    transcripts
+                        .and_then(|tcs| tcs.first())
+                        .and_then(|best| yes_or_no(&best.words, &self.config.listen)),
+                ),
             };
+            println!("Handing {:?} back to the command", reply);
+            self.state = State::ExecingCommand {
+                script,
+                reply: Some(reply),
+            };
+        }
+    }
+
+    /// Take the running command out of the state, so it can be put somewhere else
+    fn take_script(&mut self) -> Option<Script> {
+        match mem::replace(&mut self.state, State::new_idle(self.mic_sample_rate as f64)) {
+            State::ExecingCommand { script, .. } => Some(script),
+            _ => None,
+        }
+    }
+
+    /// Move on to the next command they asked for, or go back to sleep if that was the last one.
+    fn next_command(&mut self) {
+        match self.queue.pop_front() {
+            Some(command) => {
+                println!("On to the next command");
+                self.state = State::new_searching_for_command(command);
+            }
+            None => self.state = State::new_idle(self.mic_sample_rate as f64),
+        }
+    }
+
+    /// Go back to waiting for the wake phrase, forgetting about anything else they asked for.
+    fn go_idle(&mut self) {
+        self.queue.clear();
+        self.state = State::new_idle(self.mic_sample_rate as f64);
+    }
+
+    /// Give up on this command and go back to waiting for the wake phrase.
+    fn dismiss(&mut self) -> Result<(), String> {
+        self.feedback(Earcon::Dismissed)?;
+        self.go_idle();
+        Ok(())
+    }
+
+    /// Runs DeepSpeech over some audio and looks for the wake phrase in it.
+    /// If it's there, returns the words after it in each transcript that had it.
+    fn listen_for_wake_phrase<I>(
+        speech_model: &mut Model,
+        audio_data: I,
+        mic_sample_rate: u32,
+        wake: &WakeConfig,
+    ) -> Result<Option<Vec<Transcript>>, String>
+    where
+        I: IntoIterator<Item = f32>,
+    {
+        let audio_data = audio_data.into_iter().collect::<Vec<_>>();
+        let seconds = audio_data.len() as f64 / mic_sample_rate as f64;
+        let (speech, dur) = MegaState::text_to_speech(speech_model, audio_data, mic_sample_rate)?;
+        print!(
+            "in {:.2} seconds ({:.0}% of RT): ",
+            dur.as_secs_f64(),
+            100.0 * dur.as_secs_f64() / seconds
+        );
+
+        let mut first_match = None;
+        let mut rests = Vec::new();
+        for tc in speech.transcripts() {
+            if first_match.is_none() {
+                print!("{}, ", tc);
+            }
+            // Confusingly, tc.tokens() yields the separate letters.
+            // Perhaps in other natlangs they mean something different?
+            let transcript = Transcript::from_deepspeech(tc)?;
+            if let Some(wake_match) =
+                phrases::find_phrase(&transcript.words, &wake.phrases, wake.sensitivity)
+            {
+                rests.push(transcript.skip(wake_match.end));
+                if first_match.is_none() {
+                    first_match = Some(wake_match);
+                }
+            }
+        }
+
+        match first_match {
+            Some(wake_match) => {
+                println!(
+                    "Found {:?} (score {:.2})!",
+                    wake.phrases[wake_match.phrase], wake_match.score
+                );
+                Ok(Some(rests))
+            }
+            None => {
+                println!();
+                Ok(None)
+            }
         }
     }
 
+    /// Runs DeepSpeech over what they said and splits up all its guesses into words.
+    fn transcribe(
+        speech_model: &mut Model,
+        audio: Vec<f32>,
+        mic_sample_rate: u32,
+    ) -> Result<Vec<Transcript>, String> {
+        let seconds = audio.len() as f64 / mic_sample_rate as f64;
+        let (speech, dur) = MegaState::text_to_speech(speech_model, audio, mic_sample_rate)?;
+        println!(
+            "in {:.2} seconds ({:.0}% of RT):",
+            dur.as_secs_f64(),
+            100.0 * dur.as_secs_f64() / seconds
+        );
+        speech
+            .transcripts()
+            .iter()
+            .map(Transcript::from_deepspeech)
+            .collect()
+    }
+
     /// Does text-to-speech
     fn text_to_speech<I>(
         speech_model: &mut Model,
@@ -423,18 +980,35 @@ impl MegaState {
         Ok((speech, elapsed))
     }
 
-    /// Makes Mega say something
-    fn speak<S>(&mut self, msg: S) -> Result<(), String>
+    /// Lets the user know what's going on, with words or a chime or both
+    fn feedback(&mut self, event: Earcon) -> Result<(), String> {
+        let style = self.config.feedback.style;
+        if style == FeedbackStyle::Earcon || style == FeedbackStyle::Both {
+            self.play(self.earcons.get(event))?;
+        }
+        if style == FeedbackStyle::Spoken || style == FeedbackStyle::Both {
+            self.speak(event.phrase())?;
+        }
+        Ok(())
+    }
+
+    /// Plays some audio out of the speakers
+    fn play(&self, audio: Vec<f32>) -> Result<(), String> {
+        self.speaker_sender.send(audio).map_err(|err| err.to_string())
+    }
+
+    /// Makes Mega say something. Returns the id it'll show up as in `spoken_receiver` once it's said.
+    fn speak<S>(&mut self, msg: S) -> Result<u64, String>
     where
         S: Into<String>,
     {
-        self.synther_sender.send(msg.into()).map_err(|err| err.to_string())
+        self.voice.say(msg)
     }
 }
 
 /// Used for MegaState's state machine
 enum State {
-    /// Waiting for "Mega"
+    /// Waiting for the wake phrase
     Idle {
         /// Buffers the audio heard
         audio_buffer: VecDeque<f32>,
@@ -445,27 +1019,28 @@ enum State {
         /// Keeps track of whether we've gone over the loudness threshold.
         /// false if we haven't passed it; true if we have
         crossed_loudness: bool,
+        /// If the keyword spotter has heard the wake word, how many samples ago
+        spotted_for: Option<usize>,
+        /// Everything heard since just before the keyword spotter heard the wake word
+        spotted_audio: VecDeque<f32>,
+        /// How long (in samples) `spotted_audio` can get
+        spotted_buf_size: usize,
     },
-    /// Heard "Mega", now waiting for commands
-    HeardTrigger {
-        // Hm these comments look familar
-        /// Buffers the audio heard
-        audio_buffer: VecDeque<f32>,
-        /// How long (in samples) the buffered audio should be
-        buf_size: usize,
-        /// How much of the newest of the audio we should check for loudness
-        loudness_check_size: usize,
-        /// Keeps track of whether we've gone over the loudness threshold.
-        /// false if we haven't passed it; true if we have
-        crossed_loudness: bool,
+    /// Heard the wake phrase, now waiting for commands
+    HeardTrigger { listener: Listener },
+    /// Asked the user something, now waiting for the answer.
+    /// They don't need to say the wake phrase again.
+    Dialog {
+        listener: Listener,
+        purpose: DialogPurpose,
     },
     /// Searching the file tree for a command to execute
-    SearchingForCommand { command: Vec<Vec<Option<String>>> },
+    SearchingForCommand { command: Lattice },
     /// Executing the command
-    ExecingCommand { 
-        path: PathBuf, 
-        args: Vec<Vec<String>>,
-        lua_state: Lua,
+    ExecingCommand {
+        script: Script,
+        /// What to hand it when it picks up again
+        reply: Option<Reply>,
     },
 }
 
@@ -479,51 +1054,269 @@ impl State {
             loudness_check_size,
             audio_buffer,
             crossed_loudness: false,
+            spotted_for: None,
+            spotted_audio: VecDeque::new(),
+            spotted_buf_size: (sample_rate * SPOTTED_BUFFER_SIZE_SECONDS) as usize,
+        }
+    }
+    fn new_heard_trigger(sample_rate: f64, timeout_seconds: f64) -> Self {
+        State::HeardTrigger {
+            listener: Listener::new(sample_rate, timeout_seconds),
         }
     }
-    fn new_heard_trigger(sample_rate: f64) -> Self {
+    fn new_searching_for_command(command: Lattice) -> Self {
+        State::SearchingForCommand { command }
+    }
+    /// Whether commands in the background can go ahead: not while someone might be talking to Mega
+    fn lets_background_run(&self) -> bool {
+        match self {
+            // They might be in the middle of saying the wake phrase (and a command after it)
+            State::Idle {
+                crossed_loudness,
+                spotted_for,
+                ..
+            } => !crossed_loudness && spotted_for.is_none(),
+            State::ExecingCommand { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// What Mega asked about, and so what to do with the answer
+enum DialogPurpose {
+    /// Asked which of a few commands they meant
+    Disambiguate {
+        /// What they said the first time, for the arguments
+        command: Lattice,
+        choices: Vec<Candidate>,
+    },
+    /// Asked for the first of the command's missing slots
+    FillSlots {
+        found: Candidate,
+        args: Vec<Vec<String>>,
+        filled: Filled,
+    },
+    /// A command asked something with `Mega.ask` or `Mega.confirm`
+    Script { script: Script, request: Request },
+}
+
+/// What a `Listener` made of the latest audio
+enum Heard {
+    /// Still waiting
+    Nothing,
+    /// Nobody said anything before the timeout
+    Silence,
+    /// They said something, and they're done now. Here's the audio from when they started.
+    Speech(Vec<f32>),
+}
+
+/// Waits for someone to say something, then hands over the audio.
+struct Listener {
+    /// Buffers the audio heard
+    audio_buffer: VecDeque<f32>,
+    /// How long (in samples) the buffered audio should be
+    buf_size: usize,
+    /// How much of the newest of the audio we should check for loudness
+    loudness_check_size: usize,
+    /// Keeps track of whether we've gone over the loudness threshold.
+    /// false if we haven't passed it; true if we have
+    crossed_loudness: bool,
+    /// How long (in samples) we've been waiting for them to start talking
+    waited: usize,
+    /// How long (in samples) to wait for them to start talking before giving up
+    timeout: usize,
+    /// How many more samples to throw away before listening (so we don't hear ourselves)
+    deaf: usize,
+    /// Mega's still saying this, so everything's thrown away until it's done
+    speaking: Option<u64>,
+    /// How much longer (in samples) to wait for it to be done before listening anyway
+    speaking_for: usize,
+    sample_rate: f64,
+}
+
+impl Listener {
+    fn new(sample_rate: f64, timeout_seconds: f64) -> Self {
         let buf_size = (sample_rate * COMMAND_BUFFER_SIZE_SECONDS) as usize;
         let loudness_check_size = (sample_rate * THRESHOLD_TIME_SECONDS) as usize;
         let audio_buffer = (0..buf_size).map(|_| 0.0).collect();
-
-        State::HeardTrigger {
+        Self {
+            audio_buffer,
             buf_size,
             loudness_check_size,
-            audio_buffer,
             crossed_loudness: false,
+            waited: 0,
+            timeout: (sample_rate * timeout_seconds) as usize,
+            deaf: 0,
+            speaking: None,
+            speaking_for: 0,
+            sample_rate,
         }
     }
-    fn new_searching_for_command(command: Vec<Vec<Option<String>>>) -> Self {
-        State::SearchingForCommand { command }
+
+    /// Ignore everything until Mega has finished saying something, like the question it's asking.
+    /// In case it never hears that it's done, it only waits as long as `message` could take to say.
+    fn deaf_until_spoken(mut self, id: u64, message: &str) -> Self {
+        let seconds =
+            MAX_SPEAKING_SECONDS_PER_CHAR * message.len() as f64 + MAX_SPEAKING_EXTRA_SECONDS;
+        self.speaking = Some(id);
+        self.speaking_for = (self.sample_rate * seconds) as usize;
+        self
+    }
+
+    /// Mega finished saying something; if it's what we were waiting on, get ready to listen.
+    fn spoken(&mut self, id: u64) {
+        if self.speaking == Some(id) {
+            self.speaking = None;
+            self.deaf = (self.sample_rate * ECHO_SECONDS) as usize;
+        }
     }
-    fn new_execing_command(speaker: channel::Sender<String>, path: PathBuf, args: Vec<Vec<String>>) -> Result<Self, String> {
-        // Initialize Lua
-        let lua_state = Lua::new();
-        lua_state.context(move |ctx| {
-            // Initialize the Mega api!
-            let mega_api = ctx.create_table()?;
-            // Mega.speak
-            let speak = ctx.create_function(move |_, (msg,): (String,)| {
-                    let _res = speaker.send(msg);
-                    // TODO: ERRORS?
-                    Ok(())
-            })?;
-            mega_api.set("speak", speak)?;
-
-            // Seed the random generator
-            ctx.load("math.randomseed(os.time())").set_name("random seeder")?.exec()?;
-
-            // Give Lua access to the Mega api table
-            ctx.globals().set("Mega", mega_api)?;
-            Ok(())
-        }).map_err(|err: LuaError| err.to_string())?;
-
-        Ok(State::ExecingCommand { path, args, lua_state })
+
+    /// Feed in some new audio from the mic.
+    fn listen(&mut self, new_audio: Vec<Vec<f32>>) -> Result<Heard, String> {
+        if let Some(id) = self.speaking {
+            let heard = new_audio.iter().map(Vec::len).sum::<usize>();
+            if heard < self.speaking_for {
+                self.speaking_for -= heard;
+                return Ok(Heard::Nothing);
+            }
+            println!("Never heard that Mega finished saying #{}; listening anyway", id);
+            self.spoken(id);
+            return Ok(Heard::Nothing);
+        }
+        let mut new_audio = new_audio.into_iter().flatten().collect::<Vec<_>>();
+        if self.deaf > 0 {
+            let skip = self.deaf.min(new_audio.len());
+            self.deaf -= skip;
+            new_audio.drain(..skip);
+        }
+        if !self.crossed_loudness {
+            self.waited += new_audio.len();
+        }
+
+        // Buffer in the new audio
+        buffer_audio(&mut self.audio_buffer, std::iter::once(new_audio), self.buf_size);
+
+        // See if it's LOUD ENOUGH to warrant trying to scan for words
+        let loudness: f64 = self
+            .audio_buffer
+            .iter()
+            .rev()
+            .take(self.loudness_check_size)
+            .fold(0.0, |acc, &sample| acc + sample.abs() as f64);
+        let avg_loudness = loudness / self.loudness_check_size as f64;
+        // println!("Loudness: {}", avg_loudness);
+
+        if !self.crossed_loudness && avg_loudness >= ACTIVATION_THRESHOLD {
+            // OK, it's worth listening!
+            self.crossed_loudness = true;
+            Ok(Heard::Nothing)
+        } else if self.crossed_loudness && avg_loudness < ACTIVATION_THRESHOLD {
+            // We're done speaking; let's-a go!
+            self.crossed_loudness = false;
+
+            // Send only the parts starting when it goes above the threshold to DeepSpeech
+            let loudness_check_size = self.loudness_check_size;
+            let audio_buffer = &self.audio_buffer;
+            let mut running_loudness = 0.0;
+            let start_index = audio_buffer
+                .iter()
+                .enumerate()
+                .find_map(|(idx, &sample)| {
+                    // Compute the running average
+                    // Add the newest divided by the length,
+                    // subtract the oldest divided by the length.
+                    running_loudness += (sample.abs()
+                        - match idx.checked_sub(loudness_check_size) {
+                            Some(idx) => audio_buffer[idx],
+                            None => 0.0,
+                        }
+                        .abs())
+                        / loudness_check_size as f32;
+                    // Check if we're loud enough
+                    if running_loudness >= ACTIVATION_THRESHOLD as f32 {
+                        Some(idx.saturating_sub(loudness_check_size / 2))
+                    } else {
+                        None
+                    }
+                })
+                .ok_or("Somehow tried to process a command both loud enough and not loud enough")?;
+
+            print!("Starting at index {}... ", start_index);
+            flush();
+            // self.speaker_sender.send(audio_buffer.iter().skip(start_index).cloned().collect()).map_err(|err| err.to_string())?;
+            Ok(Heard::Speech(audio_buffer.iter().skip(start_index).cloned().collect()))
+        } else if !self.crossed_loudness && self.waited >= self.timeout {
+            Ok(Heard::Silence)
+        } else {
+            Ok(Heard::Nothing)
+        }
     }
 }
 
 // Helper functions
 
+/// Figure out which of the `choices` they picked.
+/// They can say (something like) the choice itself, or "the first one", "the second one"...
+fn pick_choice(transcripts: &[Transcript], choices: &[String], sensitivity: f64) -> Option<usize> {
+    let mut votes = vec![0.0; choices.len()];
+    for (transcript, weight) in transcripts.iter().zip(lattice::weights(transcripts)) {
+        let by_name = phrases::find_phrase(&transcript.words, choices, sensitivity);
+        let by_position = transcript.words.iter().find_map(|word| {
+            ORDINALS
+                .iter()
+                .position(|ordinals| ordinals.contains(&phrases::normalize(word).as_str()))
+                .filter(|&idx| idx < choices.len())
+        });
+        match (by_name, by_position) {
+            (Some(found), _) => votes[found.phrase] += weight * found.score,
+            (None, Some(idx)) => votes[idx] += weight,
+            (None, None) => {}
+        }
+    }
+    votes
+        .iter()
+        .enumerate()
+        .filter(|(_, &vote)| vote > 0.0)
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(idx, _)| idx)
+}
+
+/// Did they call the whole thing off?
+/// The cancel phrase has to come first, so commands can still take "cancel" as an argument.
+fn is_cancel(words: &[String], listen: &ListenConfig) -> bool {
+    match phrases::find_phrase(words, &listen.cancel_phrases, listen.cancel_sensitivity) {
+        Some(cancel_match) => cancel_match.start == 0,
+        None => false,
+    }
+}
+
+/// What to call a command out loud, like "good morning" for `good_morning.lua`,
+/// or "game" for `game/index.lua`
+fn command_name(path: &Path) -> String {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy());
+    let name = match stem {
+        Some(stem) if commands::index::DEFAULT_NAMES.contains(&&*stem) => path
+            .parent()
+            .and_then(|folder| folder.file_name())
+            .map(|name| name.to_string_lossy()),
+        stem => stem,
+    };
+    name.unwrap_or_default().replace(['_', '-'], " ")
+}
+
+/// Whether they said yes or no, for `Mega.confirm`. None if it wasn't either.
+fn yes_or_no(words: &[String], listen: &ListenConfig) -> Option<bool> {
+    let yes = phrases::find_phrase(words, &listen.yes_phrases, listen.yes_no_sensitivity);
+    let no = phrases::find_phrase(words, &listen.no_phrases, listen.yes_no_sensitivity);
+    match (yes, no) {
+        (Some(_), None) => Some(true),
+        (None, Some(_)) => Some(false),
+        // "yeah no", or the like
+        (Some(yes), Some(no)) if yes.score != no.score => Some(yes.score > no.score),
+        _ => None,
+    }
+}
+
 /// Add new audio data to the VecDeque, and pop data from the front until it's the given size.
 fn buffer_audio<T, I>(buffer: &mut VecDeque<T>, new_data: I, buf_size: usize)
 where
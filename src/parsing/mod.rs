@@ -0,0 +1,6 @@
+//! Making sense of the words passed to commands.
+
+pub mod numbers;
+pub mod slots;
+pub mod spelling;
+pub mod time;
@@ -0,0 +1,532 @@
+//! Turning spoken numbers back into numbers.
+//! DeepSpeech writes out "twenty three" instead of "23", so every command that wants a number
+//! would otherwise need its own table of number words.
+
+use crate::phrases;
+
+/// Number words that just are a digit
+const UNITS: &[(&str, f64)] = &[
+    ("zero", 0.0),
+    ("one", 1.0),
+    ("two", 2.0),
+    ("three", 3.0),
+    ("four", 4.0),
+    ("five", 5.0),
+    ("six", 6.0),
+    ("seven", 7.0),
+    ("eight", 8.0),
+    ("nine", 9.0),
+];
+const TEENS: &[(&str, f64)] = &[
+    ("ten", 10.0),
+    ("eleven", 11.0),
+    ("twelve", 12.0),
+    ("thirteen", 13.0),
+    ("fourteen", 14.0),
+    ("fifteen", 15.0),
+    ("sixteen", 16.0),
+    ("seventeen", 17.0),
+    ("eighteen", 18.0),
+    ("nineteen", 19.0),
+];
+const TENS: &[(&str, f64)] = &[
+    ("twenty", 20.0),
+    ("thirty", 30.0),
+    ("forty", 40.0),
+    ("fifty", 50.0),
+    ("sixty", 60.0),
+    ("seventy", 70.0),
+    ("eighty", 80.0),
+    ("ninety", 90.0),
+];
+/// Words that multiply everything before them. "hundred" is handled on its own,
+/// since it can come before the others ("two hundred thousand").
+const SCALES: &[(&str, f64)] = &[
+    ("thousand", 1e3),
+    ("million", 1e6),
+    ("billion", 1e9),
+    ("trillion", 1e12),
+];
+/// How much the best guess at a word has to sound like a number word further down the guesses
+/// to get swapped for it. "to" and "two" make it; "sides" and "six" don't.
+const SOUNDS_LIKE_NUMBER: f64 = 0.65;
+
+/// Ordinals that aren't just the number with "th" on the end
+const IRREGULAR_ORDINALS: &[(&str, &str)] = &[
+    ("first", "one"),
+    ("second", "two"),
+    ("third", "three"),
+    ("fifth", "five"),
+    ("eighth", "eight"),
+    ("ninth", "nine"),
+    ("twelfth", "twelve"),
+];
+
+/// A number someone said
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Number {
+    pub value: f64,
+    /// Whether it was like "third" instead of "three"
+    pub ordinal: bool,
+}
+
+/// What part a word plays in a number
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Word {
+    Unit(f64),
+    Teen(f64),
+    Tens(f64),
+    Hundred,
+    Scale(f64),
+    Dozen,
+    /// "a", as in "a hundred" or "a dozen"
+    A,
+    /// "and", as in "one hundred and five"
+    And,
+    /// "point", as in "two point five"
+    Point,
+    /// "minus" or "negative"
+    Minus,
+    /// Something that was already written as digits, like "23"
+    Literal(f64),
+}
+
+impl Word {
+    /// Words that are worth something on their own (not just glue like "and")
+    fn has_value(self) -> bool {
+        !matches!(self, Word::A | Word::And | Word::Point | Word::Minus)
+    }
+}
+
+/// What came last, and so what's allowed next
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Last {
+    Start,
+    Minus,
+    A,
+    Unit,
+    Teen,
+    Tens,
+    Hundred,
+    Scale,
+    And,
+}
+
+/// Parses `words` as one number, like `["one", "hundred", "and", "five"]`.
+/// Returns None if it isn't a number, or if there's anything left over after the number.
+pub fn parse_number<S: AsRef<str>>(words: &[S]) -> Option<Number> {
+    match parse_prefix(words) {
+        Some((number, len)) if len == words.len() => Some(number),
+        _ => None,
+    }
+}
+
+/// Finds all the numbers in the arguments, looking through every guess at each one.
+pub fn find_numbers(columns: &[Vec<String>]) -> Vec<Number> {
+    let words = number_words(columns);
+    let mut numbers = Vec::new();
+    let mut idx = 0;
+    while idx < words.len() {
+        match parse_prefix(&words[idx..]) {
+            Some((number, len)) => {
+                numbers.push(number);
+                idx += len;
+            }
+            None => idx += 1,
+        }
+    }
+    numbers
+}
+
+/// Picks one guess at each word, going for number words where it looks like one was misheard.
+/// The best guess at a word is used if it's a number word. Otherwise it's swapped for
+/// the first guess that is, but only if the two sound alike, so a word that was heard fine
+/// ("roll four dice with twenty sides") doesn't get turned into a number ("six").
+pub fn number_words(columns: &[Vec<String>]) -> Vec<&str> {
+    columns
+        .iter()
+        .map(|column| {
+            let best = column.first().map(String::as_str).unwrap_or_default();
+            if classify(best).is_some() {
+                return best;
+            }
+            // Don't go digging for "and"s and "a"s, though; those are too easy to mishear into
+            column
+                .iter()
+                .map(String::as_str)
+                .find(|word| {
+                    classify(word).is_some_and(Word::has_value)
+                        && phrases::word_similarity(best, word) >= SOUNDS_LIKE_NUMBER
+                })
+                .unwrap_or(best)
+        })
+        .collect()
+}
+
+/// Parses as long a number as it can from the start of `words`.
+/// Returns the number and how many words it took up.
+pub fn parse_prefix<S: AsRef<str>>(words: &[S]) -> Option<(Number, usize)> {
+    let mut negative = false;
+    // Everything before the last "thousand"/"million"/etc
+    let mut total = 0.0;
+    // Everything after it
+    let mut chunk = 0.0;
+    // The last of "thousand"/"million"/etc, so they have to go down
+    let mut last_scale = f64::INFINITY;
+    let mut last = Last::Start;
+    // The longest number we've seen so far, in case the next few words don't pan out
+    let mut best: Option<(Number, usize)> = None;
+
+    let value = |negative: bool, amount: f64| if negative { -amount } else { amount };
+
+    for (idx, word) in words.iter().enumerate() {
+        let word = word.as_ref();
+        let (kind, ordinal) = match classify(word) {
+            Some(kind) => (kind, false),
+            None => match ordinal(word) {
+                Some(kind) => (kind, true),
+                None => break,
+            },
+        };
+
+        // Some words always end the number, and have to be dealt with differently
+        match (kind, last) {
+            (Word::Literal(literal), Last::Start) | (Word::Literal(literal), Last::Minus) => {
+                let number = Number {
+                    value: value(negative, literal),
+                    ordinal,
+                };
+                return Some((number, idx + 1));
+            }
+            (Word::Unit(unit), Last::Start) | (Word::Unit(unit), Last::Minus) if unit == 0.0 => {
+                // Nobody says "zero hundred"
+                let number = Number {
+                    value: 0.0,
+                    ordinal,
+                };
+                return Some((number, idx + 1));
+            }
+            (Word::Dozen, Last::A)
+            | (Word::Dozen, Last::Unit)
+            | (Word::Dozen, Last::Teen)
+            | (Word::Dozen, Last::Tens)
+                if total == 0.0 =>
+            {
+                let dozens = if last == Last::A { 1.0 } else { chunk };
+                let number = Number {
+                    value: value(negative, dozens * 12.0),
+                    ordinal,
+                };
+                return Some((number, idx + 1));
+            }
+            (Word::Point, Last::A) | (Word::Point, Last::And) | (Word::Point, Last::Minus) => break,
+            (Word::Point, _) => {
+                return match decimals(&words[idx + 1..]) {
+                    Some((fraction, len)) => {
+                        let number = Number {
+                            value: value(negative, total + chunk + fraction),
+                            ordinal: false,
+                        };
+                        Some((number, idx + 1 + len))
+                    }
+                    None => best,
+                };
+            }
+            _ => {}
+        }
+
+        last = match (kind, last) {
+            (Word::Minus, Last::Start) => {
+                negative = true;
+                Last::Minus
+            }
+            (Word::A, Last::Start) => Last::A,
+            (Word::Unit(unit), Last::Start)
+            | (Word::Unit(unit), Last::Minus)
+            | (Word::Unit(unit), Last::Tens)
+            | (Word::Unit(unit), Last::Hundred)
+            | (Word::Unit(unit), Last::Scale)
+            | (Word::Unit(unit), Last::And)
+                if unit > 0.0 =>
+            {
+                chunk += unit;
+                Last::Unit
+            }
+            (Word::Teen(teen), Last::Start)
+            | (Word::Teen(teen), Last::Minus)
+            | (Word::Teen(teen), Last::Hundred)
+            | (Word::Teen(teen), Last::Scale)
+            | (Word::Teen(teen), Last::And) => {
+                chunk += teen;
+                Last::Teen
+            }
+            (Word::Tens(tens), Last::Start)
+            | (Word::Tens(tens), Last::Minus)
+            | (Word::Tens(tens), Last::Hundred)
+            | (Word::Tens(tens), Last::Scale)
+            | (Word::Tens(tens), Last::And) => {
+                chunk += tens;
+                Last::Tens
+            }
+            (Word::Hundred, Last::Start)
+            | (Word::Hundred, Last::Minus)
+            | (Word::Hundred, Last::A) => {
+                chunk = 100.0;
+                Last::Hundred
+            }
+            // "nineteen hundred" is fine, "three hundred twenty hundred" isn't
+            (Word::Hundred, Last::Unit)
+            | (Word::Hundred, Last::Teen)
+            | (Word::Hundred, Last::Tens)
+                if chunk < 100.0 =>
+            {
+                chunk *= 100.0;
+                Last::Hundred
+            }
+            (Word::Scale(scale), Last::Start)
+            | (Word::Scale(scale), Last::Minus)
+            | (Word::Scale(scale), Last::A) => {
+                total += scale;
+                last_scale = scale;
+                Last::Scale
+            }
+            (Word::Scale(scale), Last::Unit)
+            | (Word::Scale(scale), Last::Teen)
+            | (Word::Scale(scale), Last::Tens)
+            | (Word::Scale(scale), Last::Hundred)
+                if scale < last_scale =>
+            {
+                total += chunk * scale;
+                chunk = 0.0;
+                last_scale = scale;
+                Last::Scale
+            }
+            (Word::And, Last::Hundred) | (Word::And, Last::Scale) => Last::And,
+            _ => break,
+        };
+
+        match last {
+            // These can't end a number
+            Last::Start | Last::Minus | Last::A | Last::And => {}
+            _ => {
+                let number = Number {
+                    value: value(negative, total + chunk),
+                    ordinal,
+                };
+                best = Some((number, idx + 1));
+            }
+        }
+        if ordinal {
+            // Nothing comes after "third"
+            break;
+        }
+    }
+    best
+}
+
+/// Reads the digits after "point", like the "five" in "two point five".
+/// Returns the fraction and how many words it took up.
+fn decimals<S: AsRef<str>>(words: &[S]) -> Option<(f64, usize)> {
+    let mut fraction = 0.0;
+    let mut place = 0.1;
+    let mut len = 0;
+    for word in words {
+        let digit = match phrases::normalize(word.as_ref()).as_str() {
+            "oh" => 0.0,
+            other => match classify(other) {
+                Some(Word::Unit(unit)) => unit,
+                _ => break,
+            },
+        };
+        fraction += digit * place;
+        place /= 10.0;
+        len += 1;
+    }
+    if len == 0 {
+        None
+    } else {
+        Some((fraction, len))
+    }
+}
+
+/// Works out what part a word plays in a number, if any
+fn classify(word: &str) -> Option<Word> {
+    if let Ok(literal) = word.trim().parse::<f64>() {
+        if literal.is_finite() {
+            return Some(Word::Literal(literal));
+        }
+    }
+    let word = phrases::normalize(word);
+    let lookup = |table: &[(&str, f64)]| {
+        table
+            .iter()
+            .find(|(name, _)| *name == word)
+            .map(|&(_, value)| value)
+    };
+    if let Some(unit) = lookup(UNITS) {
+        return Some(Word::Unit(unit));
+    }
+    if let Some(teen) = lookup(TEENS) {
+        return Some(Word::Teen(teen));
+    }
+    if let Some(tens) = lookup(TENS) {
+        return Some(Word::Tens(tens));
+    }
+    if let Some(scale) = lookup(SCALES) {
+        return Some(Word::Scale(scale));
+    }
+    match word.as_str() {
+        "hundred" => Some(Word::Hundred),
+        "dozen" => Some(Word::Dozen),
+        "a" | "an" => Some(Word::A),
+        "and" => Some(Word::And),
+        "point" => Some(Word::Point),
+        "minus" | "negative" => Some(Word::Minus),
+        _ => None,
+    }
+}
+
+/// Works out what number an ordinal like "third", "twentieth" or "23rd" is
+fn ordinal(word: &str) -> Option<Word> {
+    let word = phrases::normalize(word);
+    // Written as digits
+    for suffix in &["st", "nd", "rd", "th"] {
+        if let Some(digits) = word.strip_suffix(suffix) {
+            if let Ok(literal) = digits.parse::<u64>() {
+                return Some(Word::Literal(literal as f64));
+            }
+        }
+    }
+    if let Some(&(_, cardinal)) = IRREGULAR_ORDINALS.iter().find(|(name, _)| *name == word) {
+        return classify(cardinal);
+    }
+    // "twentieth" -> "twenty"
+    if let Some(stem) = word.strip_suffix("ieth") {
+        return classify(&format!("{}y", stem));
+    }
+    // "sixth" -> "six", "hundredth" -> "hundred"
+    match word.strip_suffix("th").and_then(classify) {
+        Some(Word::A) | Some(Word::And) | Some(Word::Point) | Some(Word::Minus) => None,
+        Some(Word::Literal(_)) | Some(Word::Dozen) => None,
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<&str> {
+        text.split_whitespace().collect()
+    }
+
+    #[test]
+    fn parses_numbers() {
+        let cases: &[(&str, f64, bool)] = &[
+            ("zero", 0.0, false),
+            ("seven", 7.0, false),
+            ("thirteen", 13.0, false),
+            ("twenty three", 23.0, false),
+            ("a hundred", 100.0, false),
+            ("a hundred and five", 105.0, false),
+            ("one hundred twenty", 120.0, false),
+            ("nineteen hundred", 1900.0, false),
+            ("two hundred thousand", 200_000.0, false),
+            ("six million four hundred and six", 6_000_406.0, false),
+            ("a thousand", 1000.0, false),
+            ("a dozen", 12.0, false),
+            ("two dozen", 24.0, false),
+            ("two point five", 2.5, false),
+            ("three point one two", 3.12, false),
+            ("one point oh five", 1.05, false),
+            ("minus four", -4.0, false),
+            ("negative twenty", -20.0, false),
+            ("23", 23.0, false),
+            ("minus 7", -7.0, false),
+            ("first", 1.0, true),
+            ("third", 3.0, true),
+            ("twelfth", 12.0, true),
+            ("twentieth", 20.0, true),
+            ("twenty first", 21.0, true),
+            ("one hundredth", 100.0, true),
+            ("23rd", 23.0, true),
+        ];
+        for &(text, value, ordinal) in cases {
+            let number = parse_number(&words(text));
+            assert!(number.is_some(), "{:?} isn't a number", text);
+            let number = number.unwrap();
+            assert!(
+                (number.value - value).abs() < 1e-9,
+                "{:?} came out as {}, not {}",
+                text,
+                number.value,
+                value
+            );
+            assert_eq!(number.ordinal, ordinal, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_things_that_arent_numbers() {
+        let cases = &[
+            "",
+            "banana",
+            "and",
+            "a",
+            "minus",
+            "five banana",
+            "twenty twenty",
+            "three hundred twenty hundred",
+            "thousand million",
+            "third fourth",
+        ];
+        for text in cases {
+            assert_eq!(parse_number(&words(text)), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn mixes_digits_and_words() {
+        // Digits end a number, so "23 thousand" is 23 followed by something else
+        assert_eq!(
+            parse_prefix(&words("23 thousand")),
+            Some((
+                Number {
+                    value: 23.0,
+                    ordinal: false
+                },
+                1
+            ))
+        );
+        let columns = vec![
+            vec!["roll".to_string()],
+            vec!["4".to_string()],
+            vec!["dice".to_string()],
+            vec!["with".to_string()],
+            vec!["twenty".to_string()],
+            vec!["sides".to_string(), "six".to_string()],
+        ];
+        let values = find_numbers(&columns)
+            .iter()
+            .map(|number| number.value)
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![4.0, 20.0]);
+    }
+
+    #[test]
+    fn prefers_number_words_among_the_guesses() {
+        let columns = vec![
+            vec!["roll".to_string()],
+            vec!["to".to_string(), "two".to_string()],
+            vec!["and".to_string()],
+            vec!["tree".to_string(), "three".to_string()],
+            vec!["dice".to_string(), "five".to_string()],
+        ];
+        // "two" gets picked over "to" and "three" over "tree", but "dice" doesn't sound like "five"
+        let values = find_numbers(&columns)
+            .iter()
+            .map(|number| number.value)
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![2.0, 3.0]);
+    }
+}
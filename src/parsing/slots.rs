@@ -0,0 +1,466 @@
+//! Slots: the arguments a command asks for by name, instead of picking through them itself.
+//! A command's entry in `_mega.toml` can describe what it expects to hear after its name:
+//!
+//! ```toml
+//! [roll]
+//! slots = ["count: number?", "dee", "sides: number"]
+//! ```
+//!
+//! Things with a `:` are slots, filled in with whatever was said there; a `?` on the end means
+//! it's fine to leave it out. Everything else is a word that should be said there.
+//! Slots can be a `number`, a `word`, some `words`, or something `spelled` out letter by letter.
+
+use super::{numbers, spelling};
+use crate::phrases;
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Filling a slot is worth this much...
+const FILLED_SCORE: f64 = 2.0;
+/// ... leaving out a slot that has to be there costs this much ...
+const MISSING_PENALTY: f64 = 2.0;
+/// ... and so do words that didn't fit anywhere, and expected words that weren't said.
+const SKIPPED_WORD_PENALTY: f64 = 1.0;
+const SKIPPED_PATTERN_PENALTY: f64 = 0.5;
+
+/// What goes in a slot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlotKind {
+    /// A number like "twenty three"
+    Number,
+    /// Any one word
+    Word,
+    /// One or more words
+    Words,
+    /// Something spelled out letter by letter, like a name or a password
+    Spelled,
+}
+
+impl SlotKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "number" => Some(SlotKind::Number),
+            "word" => Some(SlotKind::Word),
+            "words" => Some(SlotKind::Words),
+            "spelled" => Some(SlotKind::Spelled),
+            _ => None,
+        }
+    }
+}
+
+/// A named argument
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slot {
+    pub name: String,
+    pub kind: SlotKind,
+    /// If this is missing, Mega will ask for it
+    pub required: bool,
+}
+
+impl Slot {
+    /// Fill this slot from everything in `args`, like when they answer Mega asking for it.
+    pub fn parse(&self, args: &[Vec<String>]) -> Option<SlotValue> {
+        match self.kind {
+            SlotKind::Number => numbers::find_numbers(args)
+                .first()
+                .map(|number| SlotValue::Number(number.value)),
+            SlotKind::Word if args.len() == 1 => args[0].first().cloned().map(SlotValue::Text),
+            SlotKind::Word => None,
+            SlotKind::Words if args.is_empty() => None,
+            SlotKind::Words => Some(SlotValue::Text(best_words(args))),
+            SlotKind::Spelled => spelling::decode_spelling_loosely(args).map(SlotValue::Text),
+        }
+    }
+
+    /// What Mega says to ask for it
+    pub fn question(&self) -> String {
+        let name = self.name.replace(['_', '-'], " ");
+        match self.kind {
+            SlotKind::Number => format!("How many {}?", name),
+            SlotKind::Word | SlotKind::Words => format!("What {}?", name),
+            SlotKind::Spelled => format!("How do you spell the {}?", name),
+        }
+    }
+}
+
+/// One part of what a command expects to hear
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// This word should be said here
+    Word(String),
+    Slot(Slot),
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim();
+        let (name, kind) = match pattern.find(':') {
+            Some(idx) => (pattern[..idx].trim(), pattern[idx + 1..].trim()),
+            None if pattern.is_empty() => return Err("there's an empty word".to_string()),
+            None => return Ok(Pattern::Word(phrases::normalize(pattern))),
+        };
+        if name.is_empty() {
+            return Err(format!("{:?} has no name", pattern));
+        }
+        let (kind, required) = match kind.strip_suffix('?') {
+            Some(kind) => (kind.trim(), false),
+            None => (kind, true),
+        };
+        let kind = SlotKind::from_name(kind).ok_or_else(|| {
+            format!(
+                "{:?} isn't a kind of slot; try number, word, words or spelled",
+                kind
+            )
+        })?;
+        Ok(Pattern::Slot(Slot {
+            name: name.to_string(),
+            kind,
+            required,
+        }))
+    }
+}
+
+/// Everything a command expects to hear after its name, in order.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct Schema {
+    pub patterns: Vec<Pattern>,
+}
+
+impl TryFrom<Vec<String>> for Schema {
+    type Error = String;
+
+    fn try_from(patterns: Vec<String>) -> Result<Self, String> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Pattern::parse(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut names = Vec::new();
+        for pattern in patterns.iter() {
+            if let Pattern::Slot(slot) = pattern {
+                if names.contains(&&slot.name) {
+                    return Err(format!("there are two slots called {:?}", slot.name));
+                }
+                names.push(&slot.name);
+            }
+        }
+        Ok(Self { patterns })
+    }
+}
+
+/// What went in a slot
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlotValue {
+    Number(f64),
+    Text(String),
+}
+
+impl fmt::Display for SlotValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SlotValue::Number(number) => write!(f, "{}", number),
+            SlotValue::Text(text) => write!(f, "{:?}", text),
+        }
+    }
+}
+
+/// The slots that got filled, and the ones that should have been but weren't
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filled {
+    pub values: BTreeMap<String, SlotValue>,
+    pub missing: Vec<Slot>,
+}
+
+/// The best way to fill the patterns from some point on, and how good it is
+type Fit = (f64, BTreeMap<String, SlotValue>);
+
+/// One way to go on from some point in the patterns and arguments
+struct Step {
+    score: f64,
+    /// How many patterns and columns it uses up
+    patterns: usize,
+    columns: usize,
+    value: Option<(String, SlotValue)>,
+}
+
+impl Step {
+    fn new(score: f64, patterns: usize, columns: usize) -> Self {
+        Self {
+            score,
+            patterns,
+            columns,
+            value: None,
+        }
+    }
+
+    fn filling(slot: &Slot, columns: usize, value: SlotValue) -> Self {
+        Self {
+            score: FILLED_SCORE,
+            patterns: 1,
+            columns,
+            value: Some((slot.name.clone(), value)),
+        }
+    }
+}
+
+impl Schema {
+    /// Fill in the slots from the arguments.
+    /// Every guess at every word is considered; words that don't fit anywhere get skipped.
+    /// `sensitivity` is how closely the expected words have to be matched, from 0 to 1.
+    pub fn fill(&self, args: &[Vec<String>], sensitivity: f64) -> Filled {
+        let number_words = numbers::number_words(args);
+        let mut memo = vec![vec![None; args.len() + 1]; self.patterns.len() + 1];
+        let (_, values) = self.fit(args, &number_words, sensitivity, 0, 0, &mut memo);
+
+        let missing = self
+            .patterns
+            .iter()
+            .filter_map(|pattern| match pattern {
+                Pattern::Slot(slot) if slot.required && !values.contains_key(&slot.name) => {
+                    Some(slot.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        Filled { values, missing }
+    }
+
+    /// The best fit of the patterns from `pattern` on to the arguments from `column` on.
+    fn fit(
+        &self,
+        args: &[Vec<String>],
+        number_words: &[&str],
+        sensitivity: f64,
+        pattern: usize,
+        column: usize,
+        memo: &mut [Vec<Option<Fit>>],
+    ) -> Fit {
+        if let Some(fit) = &memo[pattern][column] {
+            return fit.clone();
+        }
+
+        let left = args.len() - column;
+        let mut options = Vec::new();
+        match self.patterns.get(pattern) {
+            None => {
+                // Anything left over doesn't fit
+                options.push(Step::new(-SKIPPED_WORD_PENALTY * left as f64, 0, left));
+            }
+            Some(Pattern::Word(expected)) => {
+                if let Some(heard) = args.get(column) {
+                    let similarity = heard
+                        .iter()
+                        .map(|word| phrases::word_similarity(word, expected))
+                        .fold(0.0, f64::max);
+                    if similarity >= sensitivity {
+                        options.push(Step::new(similarity, 1, 1));
+                    }
+                }
+                options.push(Step::new(-SKIPPED_PATTERN_PENALTY, 1, 0));
+            }
+            Some(Pattern::Slot(slot)) => {
+                match slot.kind {
+                    SlotKind::Number => {
+                        if let Some((number, len)) = numbers::parse_prefix(&number_words[column..])
+                        {
+                            options.push(Step::filling(slot, len, SlotValue::Number(number.value)));
+                        }
+                    }
+                    SlotKind::Word => {
+                        if let Some(word) = args.get(column).and_then(|heard| heard.first()) {
+                            options.push(Step::filling(slot, 1, SlotValue::Text(word.clone())));
+                        }
+                    }
+                    SlotKind::Words => {
+                        for len in 1..=left {
+                            let value = SlotValue::Text(best_words(&args[column..column + len]));
+                            // Taking more words is better than leaving them out
+                            let mut step = Step::filling(slot, len, value);
+                            step.score += SKIPPED_WORD_PENALTY * (len - 1) as f64;
+                            options.push(step);
+                        }
+                    }
+                    SlotKind::Spelled => {
+                        for len in 1..=left {
+                            let spelled = spelling::decode_spelling(&args[column..column + len]);
+                            if let Some(spelled) = spelled {
+                                let mut step = Step::filling(slot, len, SlotValue::Text(spelled));
+                                step.score += SKIPPED_WORD_PENALTY * (len - 1) as f64;
+                                options.push(step);
+                            }
+                        }
+                    }
+                }
+                let penalty = if slot.required { MISSING_PENALTY } else { 0.0 };
+                options.push(Step::new(-penalty, 1, 0));
+            }
+        }
+        if left > 0 {
+            // This word doesn't go with anything
+            options.push(Step::new(-SKIPPED_WORD_PENALTY, 0, 1));
+        }
+
+        let mut best: Option<Fit> = None;
+        for step in options {
+            if step.patterns == 0 && step.columns == 0 {
+                continue;
+            }
+            let (rest_score, mut values) = self.fit(
+                args,
+                number_words,
+                sensitivity,
+                pattern + step.patterns,
+                column + step.columns,
+                memo,
+            );
+            let total = step.score + rest_score;
+            if best
+                .as_ref()
+                .is_none_or(|(best_score, _)| total > *best_score)
+            {
+                if let Some((name, value)) = step.value {
+                    values.insert(name, value);
+                }
+                best = Some((total, values));
+            }
+        }
+        let best = best.unwrap_or_default();
+        memo[pattern][column] = Some(best.clone());
+        best
+    }
+}
+
+/// The best guess at each word, all together
+fn best_words(args: &[Vec<String>]) -> String {
+    args.iter()
+        .filter_map(|heard| heard.first())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENSITIVITY: f64 = 0.8;
+
+    fn try_schema(patterns: &[&str]) -> Result<Schema, String> {
+        Schema::try_from(patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+    }
+
+    fn schema(patterns: &[&str]) -> Schema {
+        try_schema(patterns).unwrap()
+    }
+
+    /// One guess per word
+    fn args(text: &str) -> Vec<Vec<String>> {
+        text.split_whitespace()
+            .map(|word| vec![word.to_string()])
+            .collect()
+    }
+
+    fn number(value: f64) -> SlotValue {
+        SlotValue::Number(value)
+    }
+
+    #[test]
+    fn parses_patterns() {
+        let roll = schema(&["count: number?", "dee", "sides: number"]);
+        assert_eq!(
+            roll.patterns,
+            vec![
+                Pattern::Slot(Slot {
+                    name: "count".to_string(),
+                    kind: SlotKind::Number,
+                    required: false,
+                }),
+                Pattern::Word("dee".to_string()),
+                Pattern::Slot(Slot {
+                    name: "sides".to_string(),
+                    kind: SlotKind::Number,
+                    required: true,
+                }),
+            ]
+        );
+
+        assert!(try_schema(&["count: numbr"]).is_err());
+        assert!(try_schema(&[": number"]).is_err());
+        assert!(try_schema(&[""]).is_err());
+        assert!(try_schema(&["a: word", "a: number"]).is_err());
+    }
+
+    #[test]
+    fn fills_required_and_optional_slots() {
+        let roll = schema(&["count: number?", "dee", "sides: number"]);
+
+        let filled = roll.fill(&args("two dee six"), SENSITIVITY);
+        assert_eq!(filled.values.get("count"), Some(&number(2.0)));
+        assert_eq!(filled.values.get("sides"), Some(&number(6.0)));
+        assert!(filled.missing.is_empty());
+
+        // Leaving out the optional one is fine
+        let filled = roll.fill(&args("dee twenty"), SENSITIVITY);
+        assert_eq!(filled.values.get("count"), None);
+        assert_eq!(filled.values.get("sides"), Some(&number(20.0)));
+        assert!(filled.missing.is_empty());
+
+        // Leaving out the required one isn't
+        let filled = roll.fill(&args("three dee"), SENSITIVITY);
+        assert_eq!(filled.values.get("count"), Some(&number(3.0)));
+        assert_eq!(
+            filled
+                .missing
+                .iter()
+                .map(|slot| slot.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["sides"]
+        );
+    }
+
+    #[test]
+    fn stray_words_dont_shift_arguments() {
+        let roll = schema(&["count: number?", "dee", "sides: number"]);
+        let filled = roll.fill(&args("um two uh dee like twelve please"), SENSITIVITY);
+        assert_eq!(filled.values.get("count"), Some(&number(2.0)));
+        assert_eq!(filled.values.get("sides"), Some(&number(12.0)));
+        assert!(filled.missing.is_empty());
+    }
+
+    #[test]
+    fn fills_words() {
+        let remind = schema(&["to", "what: words"]);
+        let filled = remind.fill(&args("to buy some milk"), SENSITIVITY);
+        assert_eq!(
+            filled.values.get("what"),
+            Some(&SlotValue::Text("buy some milk".to_string()))
+        );
+    }
+
+    #[test]
+    fn fills_spelling() {
+        let call = schema(&["call", "name: spelled"]);
+        let filled = call.fill(&args("call bravo o bee"), SENSITIVITY);
+        assert_eq!(
+            filled.values.get("name"),
+            Some(&SlotValue::Text("bob".to_string()))
+        );
+    }
+
+    #[test]
+    fn looks_through_every_guess() {
+        let roll = schema(&["count: number?", "dee", "sides: number"]);
+        let heard = vec![
+            vec!["to".to_string(), "two".to_string()],
+            vec!["the".to_string(), "dee".to_string()],
+            vec!["six".to_string()],
+        ];
+        let filled = roll.fill(&heard, SENSITIVITY);
+        assert_eq!(filled.values.get("count"), Some(&number(2.0)));
+        assert_eq!(filled.values.get("sides"), Some(&number(6.0)));
+    }
+}
@@ -0,0 +1,253 @@
+//! Reading back things that were spelled out, like "k nine capital x" or "alpha bravo seven".
+//! Names, codes and passwords usually aren't words DeepSpeech knows,
+//! but the letters and digits in them are.
+
+/// How each letter might be heard, including the NATO alphabet
+const LETTERS: &[(char, &[&str])] = &[
+    ('a', &["a", "ay", "alpha", "alfa"]),
+    ('b', &["b", "be", "bee", "bravo"]),
+    ('c', &["c", "see", "sea", "charlie"]),
+    ('d', &["d", "dee", "delta"]),
+    ('e', &["e", "echo"]),
+    ('f', &["f", "ef", "eff", "foxtrot"]),
+    ('g', &["g", "gee", "golf"]),
+    ('h', &["h", "aitch", "hotel"]),
+    ('i', &["i", "eye", "aye", "india"]),
+    ('j', &["j", "jay", "juliet", "juliett"]),
+    ('k', &["k", "kay", "kilo"]),
+    ('l', &["l", "el", "ell", "elle", "lima"]),
+    ('m', &["m", "em", "mike"]),
+    ('n', &["n", "en", "november"]),
+    ('o', &["o", "oh", "owe", "oscar"]),
+    ('p', &["p", "pee", "pea", "papa"]),
+    ('q', &["q", "cue", "queue", "quebec"]),
+    ('r', &["r", "are", "romeo"]),
+    ('s', &["s", "es", "ess", "sierra"]),
+    ('t', &["t", "tee", "tea", "tango"]),
+    ('u', &["u", "you", "uniform"]),
+    ('v', &["v", "vee", "victor"]),
+    ('w', &["w", "whiskey", "whisky"]),
+    ('x', &["x", "ex", "xray"]),
+    ('y', &["y", "why", "yankee"]),
+    ('z', &["z", "zed", "zee", "zulu"]),
+];
+const DIGITS: &[(char, &str)] = &[
+    ('0', "zero"),
+    ('1', "one"),
+    ('2', "two"),
+    ('3', "three"),
+    ('4', "four"),
+    ('5', "five"),
+    ('6', "six"),
+    ('7', "seven"),
+    ('8', "eight"),
+    ('9', "nine"),
+];
+const SYMBOLS: &[(char, &[&str])] = &[
+    ('-', &["dash", "hyphen", "minus"]),
+    ('_', &["underscore"]),
+    ('.', &["dot", "period", "point"]),
+    ('@', &["at"]),
+    (' ', &["space"]),
+    ('/', &["slash"]),
+    ('#', &["hash", "pound"]),
+    ('!', &["exclamation"]),
+    ('?', &["question"]),
+    ('*', &["star", "asterisk"]),
+    ('+', &["plus"]),
+    ('$', &["dollar"]),
+];
+/// Words that don't add anything, like the "mark" in "question mark"
+const IGNORED: &[&str] = &["mark", "sign", "case", "lower", "lowercase", "small"];
+
+/// What a spelled-out word means
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Piece {
+    Char(char),
+    /// The next letter is a capital
+    Capital,
+    /// The next letter comes twice (or three times)
+    Repeat(usize),
+    /// The "ray" in "x ray"
+    Ray,
+    Ignored,
+}
+
+/// Reads back something spelled out in the arguments, looking through every guess at each word.
+/// Returns None if any of it isn't spelling.
+pub fn decode_spelling(columns: &[Vec<String>]) -> Option<String> {
+    let pieces = columns
+        .iter()
+        .map(|column| column.iter().find_map(|word| piece(word)))
+        .collect::<Option<Vec<_>>>()?;
+    assemble(&pieces)
+}
+
+/// Like `decode_spelling`, but words that aren't spelling get taken letter by letter.
+/// DeepSpeech hears letters, not words, so if it ran some of the spelling together
+/// into a word it doesn't know, the letters in it are still its best guess.
+pub fn decode_spelling_loosely(columns: &[Vec<String>]) -> Option<String> {
+    let mut pieces = Vec::new();
+    for column in columns {
+        match column.iter().find_map(|word| piece(word)) {
+            Some(piece) => pieces.push(piece),
+            None => {
+                let best = column.first()?;
+                pieces.extend(
+                    best.chars()
+                        .filter(|c| c.is_alphanumeric())
+                        .map(Piece::Char),
+                );
+            }
+        }
+    }
+    assemble(&pieces)
+}
+
+/// Puts the pieces together, applying the capitals and repeats
+fn assemble(pieces: &[Piece]) -> Option<String> {
+    let mut out = String::new();
+    let mut capital = false;
+    let mut repeat = 1;
+    for (idx, &piece) in pieces.iter().enumerate() {
+        match piece {
+            // "double u" is a w, not two u's
+            Piece::Char('u') if repeat == 2 && idx > 0 && pieces[idx - 1] == Piece::Repeat(2) => {
+                out.push(if capital { 'W' } else { 'w' });
+                capital = false;
+                repeat = 1;
+            }
+            // "five oh two" is 502
+            Piece::Char('o') if !capital && between_digits(&out, &pieces[idx + 1..]) => {
+                for _ in 0..repeat {
+                    out.push('0');
+                }
+                repeat = 1;
+            }
+            Piece::Char(c) => {
+                let c = if capital { c.to_ascii_uppercase() } else { c };
+                for _ in 0..repeat {
+                    out.push(c);
+                }
+                capital = false;
+                repeat = 1;
+            }
+            Piece::Capital => capital = true,
+            Piece::Repeat(times) => repeat = times,
+            Piece::Ray if out.ends_with(|c: char| c.eq_ignore_ascii_case(&'x')) => {}
+            Piece::Ray => return None,
+            Piece::Ignored => {}
+        }
+    }
+    if out.is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// Whether an "oh" after what's been read so far is in the middle of a number.
+/// Other "oh"s can come before the next digit, like in "five oh oh two".
+fn between_digits(out: &str, next: &[Piece]) -> bool {
+    let after = next
+        .iter()
+        .find(|piece| !matches!(piece, Piece::Char('o') | Piece::Repeat(_) | Piece::Ignored));
+    out.ends_with(|c: char| c.is_ascii_digit())
+        && matches!(after, Some(Piece::Char(c)) if c.is_ascii_digit())
+}
+
+/// Works out what a spelled-out word means, if anything
+fn piece(word: &str) -> Option<Piece> {
+    let word = word.trim().to_lowercase();
+    // Already a digit
+    if word.len() == 1 && word.chars().all(|c| c.is_ascii_digit()) {
+        return word.chars().next().map(Piece::Char);
+    }
+    let word = word
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>();
+    if let Some(&(letter, _)) = LETTERS.iter().find(|(_, names)| names.contains(&&*word)) {
+        return Some(Piece::Char(letter));
+    }
+    if let Some(&(digit, _)) = DIGITS.iter().find(|(_, name)| *name == word) {
+        return Some(Piece::Char(digit));
+    }
+    if let Some(&(symbol, _)) = SYMBOLS.iter().find(|(_, names)| names.contains(&&*word)) {
+        return Some(Piece::Char(symbol));
+    }
+    match word.as_str() {
+        "capital" | "upper" | "uppercase" | "big" => Some(Piece::Capital),
+        "double" => Some(Piece::Repeat(2)),
+        "triple" => Some(Piece::Repeat(3)),
+        "ray" => Some(Piece::Ray),
+        _ if IGNORED.contains(&word.as_str()) => Some(Piece::Ignored),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One guess per word
+    fn columns(text: &str) -> Vec<Vec<String>> {
+        text.split_whitespace()
+            .map(|word| vec![word.to_string()])
+            .collect()
+    }
+
+    #[test]
+    fn decodes_spelling() {
+        let cases = &[
+            ("b o b", "bob"),
+            ("k nine capital x", "k9X"),
+            ("alpha bravo seven", "ab7"),
+            ("x ray yankee zulu", "xyz"),
+            ("capital charlie 3 p o", "C3po"),
+            ("double l", "ll"),
+            ("b a double l", "ball"),
+            ("triple seven", "777"),
+            ("double u", "w"),
+            ("capital double u", "W"),
+            ("a dash b underscore c", "a-b_c"),
+            ("question mark", "?"),
+            ("dee e are", "der"),
+            ("five oh two", "502"),
+            ("one oh oh seven", "1007"),
+            ("nine double oh one", "9001"),
+            ("b o b one oh", "bob1o"),
+            ("two capital oh three", "2O3"),
+        ];
+        for &(text, expected) in cases {
+            let decoded = decode_spelling(&columns(text));
+            assert_eq!(decoded.as_deref(), Some(expected), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_things_that_arent_spelling() {
+        for text in &["", "hello there", "b o banana", "ray", "capital", "mark"] {
+            assert_eq!(decode_spelling(&columns(text)), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn looks_through_every_guess() {
+        let heard = vec![
+            vec!["bee".to_string()],
+            vec!["hello".to_string(), "oh".to_string()],
+            vec!["bee".to_string()],
+        ];
+        assert_eq!(decode_spelling(&heard).as_deref(), Some("bob"));
+    }
+
+    #[test]
+    fn loosely_takes_words_letter_by_letter() {
+        assert_eq!(
+            decode_spelling_loosely(&columns("k nine xq")).as_deref(),
+            Some("k9xq")
+        );
+        assert_eq!(decode_spelling_loosely(&columns("")), None);
+    }
+}
@@ -0,0 +1,725 @@
+//! Working out when "tomorrow at five pm" or "in twenty minutes" is.
+//! Everything is worked out from a `now` that gets passed in,
+//! so the same words always mean the same time for the same `now`.
+
+use super::numbers;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+
+/// What time "tomorrow" or "next tuesday" means, if they don't say
+const DEFAULT_HOUR: u32 = 9;
+/// With a day but no am or pm, hours before this are taken to be in the afternoon,
+/// since "tomorrow at five" is much more likely to be 5 pm than 5 am.
+const PM_GUESS_BEFORE: u32 = 7;
+
+const WEEKDAYS: &[(&str, Weekday)] = &[
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+];
+const MONTHS: &[&str] = &[
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+/// Parts of the day: what time they mean if that's all they say, and whether they're am or pm
+const PARTS_OF_DAY: &[(&str, u32, Meridiem)] = &[
+    ("morning", 9, Meridiem::Am),
+    ("afternoon", 15, Meridiem::Pm),
+    ("evening", 19, Meridiem::Pm),
+    ("night", 21, Meridiem::Pm),
+    ("tonight", 21, Meridiem::Pm),
+];
+/// How long each unit of time is, in seconds
+const UNITS: &[(&[&str], i64)] = &[
+    (&["second", "seconds", "sec", "secs"], 1),
+    (&["minute", "minutes", "min", "mins"], 60),
+    (&["hour", "hours", "hr", "hrs"], 60 * 60),
+    (&["day", "days"], 24 * 60 * 60),
+    (&["week", "weeks"], 7 * 24 * 60 * 60),
+];
+/// Words that can go between the parts of a time without changing it
+const FILLER: &[&str] = &["at", "on", "the", "of", "this", "in", "by", "for"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Meridiem {
+    Am,
+    Pm,
+}
+
+/// A time on the clock, like "half past three"
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ClockTime {
+    hour: u32,
+    minute: u32,
+    /// None if they didn't say, and it could be either
+    meridiem: Option<Meridiem>,
+}
+
+/// What day they said, and how to take it if that turns out to be in the past
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Day {
+    /// "today" or "tomorrow"; if that's already gone, too bad
+    Fixed(NaiveDate),
+    /// Just "tuesday"; if it's tuesday but that time's gone, they mean next week
+    Weekday(NaiveDate),
+}
+
+/// Parses a time like "tomorrow at five pm", "in twenty minutes", "next tuesday"
+/// or "half past three". Returns None if the words aren't (all) a time.
+pub fn parse_time<S: AsRef<str>>(words: &[S], now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let words = tokens(words);
+    let mut cursor = Cursor::new(&words);
+
+    // "in twenty minutes"
+    if cursor.eat("in") {
+        if let Some(duration) = duration(&mut cursor) {
+            if cursor.done() {
+                return Some(now + duration);
+            }
+        }
+    }
+    // "twenty minutes from now"
+    cursor.reset();
+    if let Some(duration) = duration(&mut cursor) {
+        if (cursor.eat_all(&["from", "now"]) || cursor.eat("later")) && cursor.done() {
+            return Some(now + duration);
+        }
+    }
+
+    // Otherwise, a day and a time of day, in either order
+    cursor.reset();
+    let mut day = None;
+    let mut clock = None;
+    let mut part_of_day = None;
+    while !cursor.done() {
+        if day.is_none() {
+            if let Some(found) = self::day(&mut cursor, now.date()) {
+                day = Some(found);
+                continue;
+            }
+        }
+        if clock.is_none() {
+            if let Some(found) = clock_time(&mut cursor) {
+                clock = Some(found);
+                continue;
+            }
+        }
+        if part_of_day.is_none() {
+            if let Some(&(name, hour, meridiem)) = PARTS_OF_DAY
+                .iter()
+                .find(|(name, _, _)| cursor.peek() == Some(name))
+            {
+                cursor.eat(name);
+                if name == "tonight" && day.is_none() {
+                    day = Some(Day::Fixed(now.date()));
+                }
+                part_of_day = Some((hour, meridiem));
+                continue;
+            }
+        }
+        if FILLER.iter().any(|filler| cursor.eat(filler)) {
+            continue;
+        }
+        return None;
+    }
+    if day.is_none() && clock.is_none() && part_of_day.is_none() {
+        return None;
+    }
+
+    let date = match day {
+        Some(Day::Fixed(date)) | Some(Day::Weekday(date)) => date,
+        None => now.date(),
+    };
+    let (hour, minute) = match clock {
+        Some(clock) => {
+            let meridiem = clock
+                .meridiem
+                .or_else(|| part_of_day.map(|(_, meridiem)| meridiem));
+            match meridiem {
+                Some(Meridiem::Am) if clock.hour == 12 => (0, clock.minute),
+                Some(Meridiem::Pm) if clock.hour < 12 => (clock.hour + 12, clock.minute),
+                Some(_) => (clock.hour, clock.minute),
+                None if clock.hour == 0 || clock.hour > 12 => (clock.hour, clock.minute),
+                None if day.is_some() => {
+                    if clock.hour < PM_GUESS_BEFORE {
+                        (clock.hour + 12, clock.minute)
+                    } else {
+                        (clock.hour, clock.minute)
+                    }
+                }
+                None => {
+                    // No day either, so they mean whichever comes next
+                    let hour = clock.hour % 12;
+                    let morning = date.and_hms_opt(hour, clock.minute, 0)?;
+                    let evening = date.and_hms_opt(hour + 12, clock.minute, 0)?;
+                    return Some(if morning > now {
+                        morning
+                    } else if evening > now {
+                        evening
+                    } else {
+                        morning + Duration::days(1)
+                    });
+                }
+            }
+        }
+        None => match part_of_day {
+            Some((hour, _)) => (hour, 0),
+            None => (DEFAULT_HOUR, 0),
+        },
+    };
+
+    let time = date.and_hms_opt(hour, minute, 0)?;
+    if time > now {
+        return Some(time);
+    }
+    match day {
+        // "at five" when it's already six means tomorrow
+        None => Some(time + Duration::days(1)),
+        Some(Day::Weekday(_)) => Some(time + Duration::weeks(1)),
+        Some(Day::Fixed(_)) => Some(time),
+    }
+}
+
+/// Parses a length of time like "twenty minutes", "an hour and a half"
+/// or "two hours and ten minutes". Returns None if the words aren't (all) a duration.
+pub fn parse_duration<S: AsRef<str>>(words: &[S]) -> Option<Duration> {
+    let words = tokens(words);
+    let mut cursor = Cursor::new(&words);
+    let duration = duration(&mut cursor)?;
+    if cursor.done() {
+        Some(duration)
+    } else {
+        None
+    }
+}
+
+/// Lowercases the words and takes the punctuation off the ends
+fn tokens<S: AsRef<str>>(words: &[S]) -> Vec<String> {
+    words
+        .iter()
+        .flat_map(|word| word.as_ref().split_whitespace())
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Keeps track of how far through the words we are
+struct Cursor<'a> {
+    words: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(words: &'a [String]) -> Self {
+        Self { words, pos: 0 }
+    }
+
+    fn reset(&mut self) {
+        self.pos = 0;
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.words.len()
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.words.get(self.pos).map(String::as_str)
+    }
+
+    /// Move past `word` if it's next
+    fn eat(&mut self, word: &str) -> bool {
+        if self.peek() == Some(word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move past all of `words` if they're next, or none of them if they aren't
+    fn eat_all(&mut self, words: &[&str]) -> bool {
+        let start = self.pos;
+        if words.iter().all(|word| self.eat(word)) {
+            true
+        } else {
+            self.pos = start;
+            false
+        }
+    }
+
+    /// Move past whichever of `words` is next
+    fn eat_any(&mut self, words: &[&str]) -> Option<&'a str> {
+        let word = self.peek()?;
+        if words.contains(&word) {
+            self.pos += 1;
+            Some(word)
+        } else {
+            None
+        }
+    }
+
+    /// Move past the day of the month, like "fifth" or "twenty one"
+    fn day_of_month(&mut self) -> Option<u32> {
+        let (number, len) = numbers::parse_prefix(&self.words[self.pos..])?;
+        if number.value.fract() == 0.0 && number.value >= 1.0 && number.value <= 31.0 {
+            self.pos += len;
+            Some(number.value as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Move past a number, but not ordinals. "thirty second" is 30 seconds, not the 32nd.
+    fn cardinal(&mut self) -> Option<f64> {
+        let rest = &self.words[self.pos..];
+        let (mut number, mut len) = numbers::parse_prefix(rest)?;
+        if number.ordinal {
+            let shorter = numbers::parse_prefix(&rest[..len - 1])?;
+            number = shorter.0;
+            len = shorter.1;
+        }
+        self.pos += len;
+        Some(number.value)
+    }
+
+    /// Move past a whole number between `min` and `max`, like an hour or a minute
+    fn whole_number(&mut self, min: u32, max: u32) -> Option<u32> {
+        let start = self.pos;
+        match self.cardinal() {
+            Some(value) if value.fract() == 0.0 && value >= min as f64 && value <= max as f64 => {
+                Some(value as u32)
+            }
+            _ => {
+                self.pos = start;
+                None
+            }
+        }
+    }
+}
+
+/// "two hours and ten minutes"
+fn duration(cursor: &mut Cursor) -> Option<Duration> {
+    let mut seconds = duration_part(cursor)?;
+    loop {
+        let start = cursor.pos;
+        let and = cursor.eat("and");
+        match duration_part(cursor) {
+            Some(more) if and || seconds > more => seconds += more,
+            _ => {
+                cursor.pos = start;
+                break;
+            }
+        }
+    }
+    Some(Duration::milliseconds((seconds * 1000.0).round() as i64))
+}
+
+/// "twenty minutes", "an hour and a half" or "half an hour", in seconds
+fn duration_part(cursor: &mut Cursor) -> Option<f64> {
+    let start = cursor.pos;
+    let amount = amount(cursor);
+    let unit = cursor.peek().and_then(|word| {
+        UNITS
+            .iter()
+            .find(|(names, _)| names.contains(&word))
+            .map(|&(_, seconds)| seconds)
+    });
+    match (amount, unit) {
+        (Some(mut amount), Some(unit)) => {
+            cursor.pos += 1;
+            // "an hour and a half"
+            if cursor.eat_all(&["and", "a", "half"]) {
+                amount += 0.5;
+            }
+            Some(amount * unit as f64)
+        }
+        _ => {
+            cursor.pos = start;
+            None
+        }
+    }
+}
+
+/// How many of some unit: "twenty", "one and a half", "a", "half an", "a couple of"
+fn amount(cursor: &mut Cursor) -> Option<f64> {
+    let start = cursor.pos;
+    if cursor.eat_all(&["a", "couple", "of"]) {
+        return Some(2.0);
+    }
+    if cursor.eat_all(&["a", "few"]) {
+        return Some(3.0);
+    }
+    // "a quarter of an hour", "half an hour"
+    cursor.eat_any(&["a", "an"]);
+    if cursor.eat("half") {
+        cursor.eat_any(&["a", "an"]);
+        return Some(0.5);
+    }
+    if cursor.eat("quarter") {
+        cursor.eat("of");
+        cursor.eat_any(&["a", "an"]);
+        return Some(0.25);
+    }
+    cursor.pos = start;
+    if cursor.eat_any(&["a", "an"]).is_some() {
+        return Some(1.0);
+    }
+    let mut amount = cursor.cardinal()?;
+    if cursor.eat_all(&["and", "a", "half"]) {
+        amount += 0.5;
+    }
+    Some(amount)
+}
+
+/// "tomorrow", "next tuesday", "may fifth"
+fn day(cursor: &mut Cursor, today: NaiveDate) -> Option<Day> {
+    let start = cursor.pos;
+    if cursor.eat("today") {
+        return Some(Day::Fixed(today));
+    }
+    if cursor.eat("tomorrow") {
+        return Some(Day::Fixed(today + Duration::days(1)));
+    }
+    if cursor.eat_all(&["day", "after", "tomorrow"]) {
+        return Some(Day::Fixed(today + Duration::days(2)));
+    }
+    if cursor.eat_all(&["next", "week"]) {
+        return Some(Day::Fixed(today + Duration::weeks(1)));
+    }
+
+    // "tuesday" is the next one that isn't gone yet; "next tuesday" isn't today
+    let next = cursor.eat("next");
+    if let Some(&(_, weekday)) = WEEKDAYS
+        .iter()
+        .find(|(name, _)| cursor.peek() == Some(name))
+    {
+        cursor.pos += 1;
+        let days_until =
+            (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        return Some(if next {
+            let days_until = if days_until == 0 { 7 } else { days_until };
+            Day::Fixed(today + Duration::days(days_until as i64))
+        } else {
+            Day::Weekday(today + Duration::days(days_until as i64))
+        });
+    }
+    cursor.pos = start;
+
+    // "may fifth" or "the fifth of may"
+    let (month, day) = if let Some(month) = month(cursor) {
+        cursor.eat("the");
+        match cursor.day_of_month() {
+            Some(day) => (month, day),
+            None => {
+                cursor.pos = start;
+                return None;
+            }
+        }
+    } else {
+        let day = cursor.day_of_month();
+        cursor.eat("of");
+        match (day, month(cursor)) {
+            (Some(day), Some(month)) => (month, day),
+            _ => {
+                cursor.pos = start;
+                return None;
+            }
+        }
+    };
+    let this_year = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+    if this_year >= today {
+        Some(Day::Fixed(this_year))
+    } else {
+        NaiveDate::from_ymd_opt(today.year() + 1, month, day).map(Day::Fixed)
+    }
+}
+
+/// Move past the name of a month, and get its number (starting at 1)
+fn month(cursor: &mut Cursor) -> Option<u32> {
+    let idx = MONTHS.iter().position(|name| cursor.peek() == Some(name))?;
+    cursor.pos += 1;
+    Some(idx as u32 + 1)
+}
+
+/// "five", "five thirty pm", "half past three", "ten to six", "noon"
+fn clock_time(cursor: &mut Cursor) -> Option<ClockTime> {
+    let start = cursor.pos;
+    if cursor.eat_any(&["noon", "midday"]).is_some() {
+        return Some(ClockTime {
+            hour: 12,
+            minute: 0,
+            meridiem: Some(Meridiem::Pm),
+        });
+    }
+    if cursor.eat("midnight") {
+        return Some(ClockTime {
+            hour: 0,
+            minute: 0,
+            meridiem: Some(Meridiem::Am),
+        });
+    }
+
+    // Written out, like "5:30"
+    if let Some(written) = cursor.peek().and_then(written_time) {
+        cursor.pos += 1;
+        let meridiem = meridiem(cursor);
+        return Some(ClockTime {
+            meridiem,
+            ..written
+        });
+    }
+
+    // "half past three", "quarter to four", "ten past six"
+    let minutes = if cursor.eat("half") {
+        Some(30)
+    } else if cursor.eat("quarter") || cursor.eat_all(&["a", "quarter"]) {
+        Some(15)
+    } else {
+        cursor.whole_number(1, 59).inspect(|_| {
+            cursor.eat_any(&["minute", "minutes"]);
+        })
+    };
+    if let Some(minutes) = minutes {
+        let past = if cursor.eat_any(&["past", "after"]).is_some() {
+            Some(true)
+        } else if cursor.eat_any(&["to", "till", "before", "of"]).is_some() {
+            Some(false)
+        } else {
+            None
+        };
+        if let Some(past) = past {
+            let named = cursor.eat_any(&["noon", "midday", "midnight"]);
+            let hour = match named {
+                Some(_) => Some(12),
+                None => cursor.whole_number(1, 12),
+            };
+            if let Some(hour) = hour {
+                let (hour, minute) = if past {
+                    (hour, minutes)
+                } else {
+                    // Back an hour around the clock face, so "quarter to one" is 12:45, not 0:45
+                    ((hour + 10) % 12 + 1, 60 - minutes)
+                };
+                let meridiem = match named {
+                    // Quarter past midnight is in the morning, and quarter to it is the night before
+                    Some("midnight") => Some(if past { Meridiem::Am } else { Meridiem::Pm }),
+                    Some(_) => Some(if past { Meridiem::Pm } else { Meridiem::Am }),
+                    None => meridiem(cursor),
+                };
+                return Some(ClockTime {
+                    hour,
+                    minute,
+                    meridiem,
+                });
+            }
+        }
+        cursor.pos = start;
+    }
+
+    // "five", "five thirty", "six oh five", "seven o'clock"
+    let hour = cursor.whole_number(0, 23)?;
+    let minute =
+        if cursor.eat_any(&["oclock", "o'clock"]).is_some() || cursor.eat_all(&["o", "clock"]) {
+            0
+        } else if cursor.eat_any(&["oh", "o"]).is_some() {
+            match cursor.whole_number(1, 9) {
+                Some(minute) => minute,
+                None => {
+                    cursor.pos = start;
+                    return None;
+                }
+            }
+        } else {
+            cursor.whole_number(0, 59).unwrap_or(0)
+        };
+    if UNITS
+        .iter()
+        .any(|(names, _)| cursor.peek().is_some_and(|word| names.contains(&word)))
+    {
+        // "five minutes" isn't a time
+        cursor.pos = start;
+        return None;
+    }
+    let meridiem = meridiem(cursor);
+    Some(ClockTime {
+        hour,
+        minute,
+        meridiem,
+    })
+}
+
+/// A time that's already written out, like "5:30" or "17:05"
+fn written_time(word: &str) -> Option<ClockTime> {
+    let colon = word.find(':')?;
+    let hour = word[..colon].parse::<u32>().ok()?;
+    let minute = word[colon + 1..].parse::<u32>().ok()?;
+    if hour > 23 || minute > 59 {
+        return None;
+    }
+    Some(ClockTime {
+        hour,
+        minute,
+        meridiem: None,
+    })
+}
+
+/// "am", "p.m.", "in the morning" and so on
+fn meridiem(cursor: &mut Cursor) -> Option<Meridiem> {
+    if cursor.eat_any(&["am", "a.m"]).is_some() || cursor.eat_all(&["a", "m"]) {
+        Some(Meridiem::Am)
+    } else if cursor.eat_any(&["pm", "p.m"]).is_some() || cursor.eat_all(&["p", "m"]) {
+        Some(Meridiem::Pm)
+    } else if cursor.eat_all(&["in", "the", "morning"]) {
+        Some(Meridiem::Am)
+    } else if cursor.eat_all(&["in", "the", "afternoon"])
+        || cursor.eat_all(&["in", "the", "evening"])
+        || cursor.eat_all(&["at", "night"])
+    {
+        Some(Meridiem::Pm)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tuesday the 10th of March 2026, at 2 pm
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, 10)
+            .unwrap()
+            .and_hms_opt(14, 0, 0)
+            .unwrap()
+    }
+
+    fn at(month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn time(text: &str) -> Option<NaiveDateTime> {
+        parse_time(&text.split_whitespace().collect::<Vec<_>>(), now())
+    }
+
+    fn duration(text: &str) -> Option<Duration> {
+        parse_duration(&text.split_whitespace().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_times() {
+        let cases = &[
+            ("in ten minutes", at(3, 10, 14, 10)),
+            ("twenty minutes from now", at(3, 10, 14, 20)),
+            ("in an hour and a half", at(3, 10, 15, 30)),
+            ("half past three", at(3, 10, 15, 30)),
+            ("quarter to four", at(3, 10, 15, 45)),
+            ("five thirty pm", at(3, 10, 17, 30)),
+            ("17:05", at(3, 10, 17, 5)),
+            ("noon", at(3, 11, 12, 0)),
+            ("tonight", at(3, 10, 21, 0)),
+            // With a day, a time without am or pm is probably in the afternoon
+            ("tomorrow at five", at(3, 11, 17, 0)),
+            ("tomorrow at eight", at(3, 11, 8, 0)),
+            ("tomorrow at five am", at(3, 11, 5, 0)),
+            ("tomorrow morning", at(3, 11, 9, 0)),
+            ("next tuesday", at(3, 17, 9, 0)),
+            ("friday evening", at(3, 13, 19, 0)),
+            ("the fifth of may", at(5, 5, 9, 0)),
+            ("tomorrow at quarter to one", at(3, 11, 12, 45)),
+            ("quarter to noon", at(3, 11, 11, 45)),
+            ("quarter past noon", at(3, 11, 12, 15)),
+            ("quarter to midnight", at(3, 10, 23, 45)),
+            ("ten past midnight", at(3, 11, 0, 10)),
+        ];
+        for &(text, expected) in cases {
+            assert_eq!(time(text), Some(expected), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn quarter_to_one_is_just_before_one() {
+        let morning = at(3, 10, 9, 0);
+        let words = ["quarter", "to", "one"];
+        assert_eq!(parse_time(&words, morning), Some(at(3, 10, 12, 45)));
+        // After lunch, the next one is in the middle of the night
+        assert_eq!(parse_time(&words, now()), Some(at(3, 11, 0, 45)));
+    }
+
+    #[test]
+    fn times_already_gone_roll_over() {
+        // Earlier today means tomorrow...
+        assert_eq!(time("at nine am"), Some(at(3, 11, 9, 0)));
+        // ... even when it could have been either am or pm
+        assert_eq!(time("half past one"), Some(at(3, 11, 1, 30)));
+        // Just a weekday that's today, but earlier, means next week
+        assert_eq!(time("tuesday at eight am"), Some(at(3, 17, 8, 0)));
+        // But they did say today
+        assert_eq!(time("today at nine am"), Some(at(3, 10, 9, 0)));
+        // Dates that are gone this year mean next year
+        assert_eq!(
+            time("january first"),
+            NaiveDate::from_ymd_opt(2027, 1, 1)
+                .unwrap()
+                .and_hms_opt(DEFAULT_HOUR, 0, 0)
+        );
+    }
+
+    #[test]
+    fn parses_durations() {
+        let cases = &[
+            ("ten seconds", 10),
+            ("twenty minutes", 20 * 60),
+            ("two hours and ten minutes", 2 * 60 * 60 + 10 * 60),
+            ("an hour and a half", 90 * 60),
+            ("half an hour", 30 * 60),
+            ("a quarter of an hour", 15 * 60),
+            ("a couple of days", 2 * 24 * 60 * 60),
+            ("thirty seconds", 30),
+        ];
+        for &(text, seconds) in cases {
+            assert_eq!(
+                duration(text),
+                Some(Duration::seconds(seconds)),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_things_that_arent_times() {
+        for text in &[
+            "",
+            "banana",
+            "in ten bananas",
+            "five minutes",
+            "at",
+            "tomorrow banana",
+        ] {
+            assert_eq!(time(text), None, "{:?}", text);
+        }
+        for text in &["", "ten", "tomorrow", "minutes", "ten minutes banana"] {
+            assert_eq!(duration(text), None, "{:?}", text);
+        }
+    }
+}
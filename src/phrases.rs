@@ -0,0 +1,353 @@
+//! Fuzzy matching of spoken phrases.
+//! DeepSpeech is very creative with its spelling, so "mega" might come out as
+//! "meg a", "mecca" or "omega". This compares both the spelling and the sound of words.
+
+/// Where a phrase was found in a list of words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhraseMatch {
+    /// Index of the phrase that matched
+    pub phrase: usize,
+    /// Index of the first word that matched
+    pub start: usize,
+    /// Index one past the last word that matched
+    pub end: usize,
+    /// How good the match was, from 0 to 1
+    pub score: f64,
+}
+
+/// Look through `words` for the best match of any of the `phrases`.
+/// Phrases can be multiple words long.
+/// `sensitivity` is the minimum score (0 to 1) to count as a match; 1 means exact matches only.
+pub fn find_phrase<S: AsRef<str>>(
+    words: &[S],
+    phrases: &[String],
+    sensitivity: f64,
+) -> Option<PhraseMatch> {
+    let words = words
+        .iter()
+        .map(|w| normalize(w.as_ref()))
+        .collect::<Vec<_>>();
+
+    let mut best: Option<PhraseMatch> = None;
+    for (phrase_idx, phrase) in phrases.iter().enumerate() {
+        let phrase_words = phrase.split_whitespace().map(normalize).collect::<Vec<_>>();
+        if phrase_words.is_empty() {
+            continue;
+        }
+        let joined_phrase = phrase_words.concat();
+        // DeepSpeech might split or join words, so try windows a little shorter and longer than the phrase
+        let min_len = phrase_words.len().saturating_sub(1).max(1);
+        let max_len = phrase_words.len() + 1;
+        for start in 0..words.len() {
+            for len in min_len..=max_len {
+                if start + len > words.len() {
+                    break;
+                }
+                let joined_window = words[start..start + len].concat();
+                let score = word_similarity(&joined_window, &joined_phrase);
+                let better = match &best {
+                    Some(b) => score > b.score,
+                    None => true,
+                };
+                if score >= sensitivity && better {
+                    best = Some(PhraseMatch {
+                        phrase: phrase_idx,
+                        start,
+                        end: start + len,
+                        score,
+                    });
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Joins things up the way you'd say them, like "a, b, or c".
+/// `conjunction` is the word before the last one, like "and" or "or".
+pub fn spoken_list<S: AsRef<str>>(items: &[S], conjunction: &str) -> String {
+    match items {
+        [] => String::new(),
+        [only] => only.as_ref().to_string(),
+        [first, second] => format!("{} {} {}", first.as_ref(), conjunction, second.as_ref()),
+        [rest @ .., last] => {
+            let rest = rest
+                .iter()
+                .map(|s| s.as_ref())
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{}, {} {}", rest, conjunction, last.as_ref())
+        }
+    }
+}
+
+/// How alike two words are, from 0 to 1.
+/// Close spellings score well on their own; phonetic agreement can lift a so-so spelling up.
+pub fn word_similarity(a: &str, b: &str) -> f64 {
+    let a = normalize(a);
+    let b = normalize(b);
+    let lexical = similarity(&a, &b);
+    let phonetic = similarity(&metaphone(&a), &metaphone(&b));
+    lexical.max((lexical + phonetic) / 2.0)
+}
+
+/// Lowercases and strips out everything that isn't a letter or digit.
+pub fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// 1 minus the edit distance, scaled by the length of the longer string.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f64 / longest as f64
+}
+
+/// The number of single-character insertions, deletions or substitutions to turn `a` into `b`.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    // Only keep one row of the table around at a time
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            let cost = if ca == cb { 0 } else { 1 };
+            row[j + 1] = (diagonal + cost).min(above + 1).min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// A (slightly simplified) Metaphone encoding of a word.
+/// Words that sound alike should get the same key, like "mega" and "mecca" both becoming "MK".
+pub fn metaphone(word: &str) -> String {
+    let mut letters = word
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+
+    // Silent or odd starting letters
+    match (letters.first(), letters.get(1)) {
+        (Some('K'), Some('N'))
+        | (Some('G'), Some('N'))
+        | (Some('P'), Some('N'))
+        | (Some('A'), Some('E'))
+        | (Some('W'), Some('R')) => {
+            letters.remove(0);
+        }
+        (Some('X'), _) => letters[0] = 'S',
+        (Some('W'), Some('H')) => {
+            letters.remove(1);
+        }
+        _ => {}
+    }
+
+    let is_vowel = |c: Option<&char>| match c {
+        Some(c) => "AEIOU".contains(*c),
+        None => false,
+    };
+    let is_front_vowel = |c: Option<&char>| match c {
+        Some(c) => "EIY".contains(*c),
+        None => false,
+    };
+
+    let mut out = String::new();
+    for (idx, &letter) in letters.iter().enumerate() {
+        let prev = if idx > 0 { letters.get(idx - 1) } else { None };
+        let next = letters.get(idx + 1);
+        let after_next = letters.get(idx + 2);
+
+        // Skip doubled letters, except C when it's about to turn into an S ("accident")
+        if prev == Some(&letter) && !(letter == 'C' && is_front_vowel(next)) {
+            continue;
+        }
+
+        match letter {
+            'A' | 'E' | 'I' | 'O' | 'U' => {
+                if idx == 0 {
+                    out.push(letter);
+                }
+            }
+            'B' => {
+                // Silent in "-mb"
+                if !(prev == Some(&'M') && next.is_none()) {
+                    out.push('B');
+                }
+            }
+            'C' => {
+                if next == Some(&'I') && after_next == Some(&'A') {
+                    out.push('X');
+                } else if next == Some(&'H') {
+                    out.push(if prev == Some(&'S') { 'K' } else { 'X' });
+                } else if is_front_vowel(next) {
+                    if prev != Some(&'S') {
+                        out.push('S');
+                    }
+                } else {
+                    out.push('K');
+                }
+            }
+            'D' => {
+                if next == Some(&'G') && is_front_vowel(after_next) {
+                    out.push('J');
+                } else {
+                    out.push('T');
+                }
+            }
+            'G' => {
+                if next == Some(&'H') && !is_vowel(after_next) && after_next.is_some() {
+                    // Silent in "-ght"
+                } else if next == Some(&'N') && (after_next.is_none() || after_next == Some(&'E')) {
+                    // Silent in "-gn" and "-gned"
+                } else if prev == Some(&'D') && is_front_vowel(next) {
+                    // Already handled by the D
+                } else if is_front_vowel(next) {
+                    out.push('J');
+                } else {
+                    out.push('K');
+                }
+            }
+            'H' => {
+                let after_modifier = match prev {
+                    Some(c) => "CSPTG".contains(*c),
+                    None => false,
+                };
+                if !after_modifier && (!is_vowel(prev) || is_vowel(next)) {
+                    out.push('H');
+                }
+            }
+            'K' => {
+                if prev != Some(&'C') {
+                    out.push('K');
+                }
+            }
+            'P' => out.push(if next == Some(&'H') { 'F' } else { 'P' }),
+            'Q' => out.push('K'),
+            'S' => {
+                if next == Some(&'H')
+                    || (next == Some(&'I')
+                        && (after_next == Some(&'O') || after_next == Some(&'A')))
+                {
+                    out.push('X');
+                } else {
+                    out.push('S');
+                }
+            }
+            'T' => {
+                if next == Some(&'I') && (after_next == Some(&'O') || after_next == Some(&'A')) {
+                    out.push('X');
+                } else if next == Some(&'H') {
+                    out.push('0');
+                } else if !(next == Some(&'C') && after_next == Some(&'H')) {
+                    out.push('T');
+                }
+            }
+            'V' => out.push('F'),
+            'W' | 'Y' => {
+                if is_vowel(next) {
+                    out.push(letter);
+                }
+            }
+            'X' => out.push_str("KS"),
+            'Z' => out.push('S'),
+            // F J L M N R sound like themselves
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phrases(list: &[&str]) -> Vec<String> {
+        list.iter().map(|phrase| phrase.to_string()).collect()
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("mega", ""), 4);
+        assert_eq!(levenshtein("mega", "mecca"), 2);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(similarity("mega", "mega"), 1.0);
+        assert_eq!(similarity("abcd", "wxyz"), 0.0);
+    }
+
+    #[test]
+    fn sounds_alike() {
+        assert_eq!(metaphone("mega"), "MK");
+        assert_eq!(metaphone("mecca"), "MK");
+        assert_eq!(metaphone("knight"), metaphone("night"));
+        assert_eq!(metaphone("phone"), "FN");
+        assert_ne!(metaphone("mega"), metaphone("banana"));
+    }
+
+    #[test]
+    fn normalizes() {
+        assert_eq!(normalize("Mega!"), "mega");
+        assert_eq!(normalize("don't"), "dont");
+        assert_eq!(normalize("R2-D2"), "r2d2");
+    }
+
+    #[test]
+    fn sound_lifts_a_so_so_spelling() {
+        let spelled = similarity("mega", "mecca");
+        assert!(word_similarity("mega", "mecca") > spelled);
+        assert!(word_similarity("mega", "banana") < 0.5);
+    }
+
+    #[test]
+    fn finds_phrases_that_were_split_up() {
+        let heard = ["hey", "meg", "a", "what", "time", "is", "it"];
+        let found = find_phrase(&heard, &phrases(&["mega"]), 0.7).unwrap();
+        assert_eq!((found.phrase, found.start, found.end), (0, 1, 3));
+        assert_eq!(found.score, 1.0);
+    }
+
+    #[test]
+    fn finds_the_best_of_several_phrases() {
+        let heard = ["okay", "computer", "lights", "off"];
+        let found = find_phrase(&heard, &phrases(&["mega", "okay computer"]), 0.7).unwrap();
+        assert_eq!((found.phrase, found.start, found.end), (1, 0, 2));
+    }
+
+    #[test]
+    fn sensitivity_of_one_means_exact() {
+        let heard = ["mecca", "time"];
+        assert!(find_phrase(&heard, &phrases(&["mega"]), 1.0).is_none());
+        assert!(find_phrase(&heard, &phrases(&["mega"]), 0.6).is_some());
+        assert!(find_phrase(&heard, &phrases(&["  "]), 0.0).is_none());
+        assert!(find_phrase::<&str>(&[], &phrases(&["mega"]), 0.0).is_none());
+    }
+
+    #[test]
+    fn short_words_need_a_close_match() {
+        let listen = crate::config::ListenConfig::default();
+        let heard = ["do", "it", "now"];
+        let no = find_phrase(&heard, &listen.no_phrases, listen.cancel_sensitivity);
+        assert_eq!(no.map(|found| found.start), Some(2));
+        assert!(find_phrase(&heard, &listen.no_phrases, listen.yes_no_sensitivity).is_none());
+        let yes = find_phrase(&heard, &listen.yes_phrases, listen.yes_no_sensitivity).unwrap();
+        assert_eq!((yes.start, yes.end), (0, 2));
+    }
+
+    #[test]
+    fn lists_things_out_loud() {
+        let none: [&str; 0] = [];
+        assert_eq!(spoken_list(&none, "or"), "");
+        assert_eq!(spoken_list(&["time"], "or"), "time");
+        assert_eq!(spoken_list(&["time", "timer"], "or"), "time or timer");
+        assert_eq!(spoken_list(&["a", "b", "c"], "and"), "a, b, and c");
+    }
+}
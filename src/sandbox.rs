@@ -0,0 +1,363 @@
+//! Keeping commands in line: which bits of Lua's standard library they get,
+//! and how long and how much memory they can use before Mega stops them.
+//! By default commands can't touch files or run programs; a command's entry in `_mega.toml`
+//! can let it, like `allow = ["files"]`.
+
+use crate::config::SandboxConfig;
+
+use rlua::{Context, Error as LuaError, Function, HookTriggers, Lua, StdLib, Table, Thread};
+use serde::Deserialize;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where `debug.traceback` is kept, out of the commands' reach
+const TRACEBACK_KEY: &str = "mega_traceback";
+
+/// How many instructions go by between checks on how long a command has been going
+const HOOK_INTERVAL: u32 = 10_000;
+
+/// The bits of `os` every command gets
+const SAFE_OS: &[&str] = &["clock", "date", "difftime", "time"];
+/// ... and the bits `files` and `processes` add
+const FILES_OS: &[&str] = &["remove", "rename", "tmpname"];
+const PROCESSES_OS: &[&str] = &["execute", "getenv"];
+/// The bits of the base library that read files, which need `files`
+const FILES_BASE: &[&str] = &["dofile", "loadfile"];
+
+/// Only lets text chunks through `load`; compiled chunks can crash Lua on purpose.
+/// And errors from running out of time or memory go straight through `pcall` and friends,
+/// so commands can't just catch them and carry on.
+const SAFE_BASE: &str = r#"
+local exhausted = ...
+local load, pcall, xpcall, resume, error = load, pcall, xpcall, coroutine.resume, error
+
+function _G.load(chunk, name, _, env)
+    return load(chunk, name, "t", env)
+end
+
+local function rethrow(ok, ...)
+    if not ok and (exhausted() or ... == "not enough memory") then
+        error((...), 0)
+    end
+    return ok, ...
+end
+
+function _G.pcall(f, ...)
+    return rethrow(pcall(f, ...))
+end
+
+function _G.xpcall(f, handler, ...)
+    return rethrow(xpcall(f, handler, ...))
+end
+
+function coroutine.resume(co, ...)
+    return rethrow(resume(co, ...))
+end
+"#;
+
+/// Something extra a command can be allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// Reading and writing files, with `io`, `dofile` and friends
+    Files,
+    /// Running other programs, with `os.execute` and `io.popen`, and reading environment variables
+    Processes,
+}
+
+/// Everything that goes into making a command's Lua state
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    pub allow: Vec<Capability>,
+    pub limits: SandboxConfig,
+}
+
+impl Sandbox {
+    /// Make a Lua state with just the libraries the command is allowed,
+    /// and the limits on how much it can do.
+    /// The `Budget` has to be reset every time the command is run for a bit.
+    pub fn lua(&self) -> Result<(Lua, Budget), String> {
+        let files = self.allow.contains(&Capability::Files);
+        let processes = self.allow.contains(&Capability::Processes);
+
+        let mut libs = StdLib::BASE
+            | StdLib::COROUTINE
+            | StdLib::TABLE
+            | StdLib::STRING
+            | StdLib::UTF8
+            | StdLib::MATH
+            | StdLib::OS;
+        if files || processes {
+            libs |= StdLib::IO;
+        }
+        // The debug library can get around everything else here, so commands never see it.
+        // Mega keeps `debug.traceback` for itself, for when a command goes wrong,
+        // and the rest goes before any command code gets to run.
+        let lua = unsafe { Lua::unsafe_new_with(libs | StdLib::DEBUG) };
+        let budget = Budget::new(&self.limits);
+
+        lua.context(|ctx| {
+            let globals = ctx.globals();
+            let traceback = globals
+                .get::<_, Table>("debug")?
+                .get::<_, Function>("traceback")?;
+            ctx.set_named_registry_value(TRACEBACK_KEY, traceback)?;
+            globals.set("debug", rlua::Nil)?;
+
+            let os = globals.get::<_, Table>("os")?;
+            let mut os_allowed = SAFE_OS.to_vec();
+            if files {
+                os_allowed.extend(FILES_OS);
+            }
+            if processes {
+                os_allowed.extend(PROCESSES_OS);
+            }
+            for pair in os.clone().pairs::<String, rlua::Value>() {
+                let (name, _) = pair?;
+                if !os_allowed.contains(&name.as_str()) {
+                    os.set(name, rlua::Nil)?;
+                }
+            }
+
+            if !files {
+                for name in FILES_BASE {
+                    globals.set(*name, rlua::Nil)?;
+                }
+                if processes {
+                    // Just `io.popen`, then
+                    let io = globals.get::<_, Table>("io")?;
+                    let popen_only = ctx.create_table()?;
+                    popen_only.set("popen", io.get::<_, rlua::Value>("popen")?)?;
+                    globals.set("io", popen_only)?;
+                }
+            } else if !processes {
+                globals.get::<_, Table>("io")?.set("popen", rlua::Nil)?;
+            }
+
+            let exhausted = budget.clone();
+            let exhausted = ctx.create_function(move |_, ()| Ok(exhausted.check().is_err()))?;
+            ctx.load(SAFE_BASE)
+                .set_name("sandbox")?
+                .call::<_, ()>(exhausted)
+        })
+        .map_err(|err: LuaError| err.to_string())?;
+
+        lua.set_memory_limit(Some(self.limits.max_memory_bytes));
+        let spent = budget.clone();
+        lua.set_hook(
+            HookTriggers {
+                every_nth_instruction: Some(HOOK_INTERVAL),
+                ..Default::default()
+            },
+            move |_, _| spent.spend(HOOK_INTERVAL as u64),
+        );
+        Ok((lua, budget))
+    }
+}
+
+/// Adds where in the command an error happened, all the way down the stack.
+/// `thread` is the coroutine it happened in.
+pub fn traceback<'lua>(ctx: Context<'lua>, thread: &Thread<'lua>, err: LuaError) -> LuaError {
+    let message = match &err {
+        LuaError::RuntimeError(message) => message.clone(),
+        other => other.to_string(),
+    };
+    let traceback = ctx
+        .named_registry_value::<_, Function>(TRACEBACK_KEY)
+        .and_then(|traceback| traceback.call::<_, String>((thread.clone(), message)));
+    match traceback {
+        Ok(traceback) => LuaError::RuntimeError(traceback),
+        // Better the error without the traceback than no error at all
+        Err(_) => err,
+    }
+}
+
+/// How much a command has done since it last stopped to wait for something.
+/// Commands can go on as long as they like in total, as long as they keep stopping
+/// (like with `Mega.sleep`) to let Mega get on with things.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    spent: Arc<Mutex<Spent>>,
+    max_instructions: u64,
+    max_time: Duration,
+}
+
+#[derive(Debug)]
+struct Spent {
+    instructions: u64,
+    since: Instant,
+    /// Why the command got stopped, if it did. Once it's out, it stays out until the next reset.
+    exhausted: Option<String>,
+}
+
+impl Budget {
+    fn new(limits: &SandboxConfig) -> Self {
+        Self {
+            spent: Arc::new(Mutex::new(Spent {
+                instructions: 0,
+                since: Instant::now(),
+                exhausted: None,
+            })),
+            max_instructions: limits.max_instructions,
+            // The config makes sure this is a sensible number of seconds
+            max_time: Duration::from_secs_f64(limits.max_seconds),
+        }
+    }
+
+    /// Start counting again, right before running the command some more
+    pub fn reset(&self) {
+        let mut spent = self.spent.lock().unwrap();
+        spent.instructions = 0;
+        spent.since = Instant::now();
+        spent.exhausted = None;
+    }
+
+    /// Whether the command's still got some budget left. It might have caught the error
+    /// from running out, so this gets checked again after it's been run.
+    pub fn check(&self) -> Result<(), String> {
+        match &self.spent.lock().unwrap().exhausted {
+            Some(why) => Err(why.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Count some instructions, and stop the command if it's been going too long
+    fn spend(&self, instructions: u64) -> Result<(), LuaError> {
+        let mut spent = self.spent.lock().unwrap();
+        spent.instructions += instructions;
+        if spent.exhausted.is_none() {
+            if spent.instructions > self.max_instructions {
+                spent.exhausted = Some(format!(
+                    "stopped after running more than {} instructions without a break",
+                    self.max_instructions
+                ));
+            } else if spent.since.elapsed() > self.max_time {
+                spent.exhausted = Some(format!(
+                    "stopped after running for more than {:.1} seconds without a break",
+                    self.max_time.as_secs_f64()
+                ));
+            }
+        }
+        match &spent.exhausted {
+            Some(why) => Err(LuaError::RuntimeError(why.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sandbox(allow: &[Capability], limits: SandboxConfig) -> (Lua, Budget) {
+        Sandbox {
+            allow: allow.to_vec(),
+            limits,
+        }
+        .lua()
+        .unwrap()
+    }
+
+    /// Runs some Lua and gets back what it returns as a string.
+    /// Errors come back with their causes, since the hook's errors are wrapped in callback errors.
+    fn eval(lua: &Lua, code: &str) -> Result<String, String> {
+        lua.context(|ctx| ctx.load(code).eval::<String>())
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    #[test]
+    fn leaves_out_what_commands_are_not_allowed() {
+        let (lua, _) = sandbox(&[], SandboxConfig::default());
+        let gone = "return tostring(io) .. tostring(os.execute) .. tostring(os.remove) .. tostring(dofile)";
+        assert_eq!(eval(&lua, gone).unwrap(), "nilnilnilnil");
+        assert_eq!(eval(&lua, "return type(os.time())").unwrap(), "number");
+
+        let (lua, _) = sandbox(&[Capability::Files], SandboxConfig::default());
+        let files = "return type(io.open) .. type(os.remove) .. tostring(io.popen)";
+        assert_eq!(eval(&lua, files).unwrap(), "functionfunctionnil");
+
+        let (lua, _) = sandbox(&[Capability::Processes], SandboxConfig::default());
+        let processes = "return type(io.popen) .. tostring(io.open) .. type(os.execute)";
+        assert_eq!(eval(&lua, processes).unwrap(), "functionnilfunction");
+    }
+
+    #[test]
+    fn only_loads_text() {
+        let (lua, _) = sandbox(&[], SandboxConfig::default());
+        assert_eq!(eval(&lua, "return load('return \"hi\"')()").unwrap(), "hi");
+        let binary = "return tostring(load(string.dump(function() end)))";
+        assert_eq!(eval(&lua, binary).unwrap(), "nil");
+    }
+
+    #[test]
+    fn stops_commands_that_go_on_too_long() {
+        let limits = SandboxConfig {
+            max_instructions: 1_000_000,
+            ..SandboxConfig::default()
+        };
+        let (lua, budget) = sandbox(&[], limits);
+        budget.reset();
+        let err = eval(&lua, "while true do end").unwrap_err();
+        assert!(err.contains("instructions"), "{}", err);
+
+        // A break sets it going again
+        budget.reset();
+        assert_eq!(
+            eval(&lua, "for i = 1, 1000 do end return 'ok'").unwrap(),
+            "ok"
+        );
+
+        let limits = SandboxConfig {
+            max_seconds: 0.0,
+            ..SandboxConfig::default()
+        };
+        let (lua, budget) = sandbox(&[], limits);
+        budget.reset();
+        let err = eval(&lua, "while true do end").unwrap_err();
+        assert!(err.contains("seconds"), "{}", err);
+    }
+
+    #[test]
+    fn stops_commands_that_use_too_much_memory() {
+        let limits = SandboxConfig {
+            max_memory_bytes: 4 * 1024 * 1024,
+            ..SandboxConfig::default()
+        };
+        let (lua, budget) = sandbox(&[], limits);
+        budget.reset();
+        assert!(eval(&lua, "return string.rep('x', 16 * 1024 * 1024)").is_err());
+        assert_eq!(eval(&lua, "return string.rep('x', 3)").unwrap(), "xxx");
+    }
+
+    #[test]
+    fn commands_cant_catch_being_stopped() {
+        let limits = SandboxConfig {
+            max_instructions: 1_000_000,
+            max_memory_bytes: 4 * 1024 * 1024,
+            ..SandboxConfig::default()
+        };
+        let (lua, budget) = sandbox(&[], limits);
+        budget.reset();
+        let caught = "while not pcall(function() while true do end end) do end return 'escaped'";
+        assert!(eval(&lua, caught).is_err());
+        // It stays stopped until it gets a break
+        assert!(budget.check().is_err());
+        budget.reset();
+        assert!(budget.check().is_ok());
+
+        let resumed = "local co = coroutine.create(function() while true do end end)
+            coroutine.resume(co)
+            return 'escaped'";
+        assert!(eval(&lua, resumed).is_err());
+        budget.reset();
+
+        let greedy = "pcall(string.rep, 'x', 16 * 1024 * 1024) return 'escaped'";
+        assert!(eval(&lua, greedy).is_err());
+
+        // Everyday errors can still be caught
+        budget.reset();
+        let everyday = "local ok, err = pcall(error, 'oops', 0) return err";
+        assert_eq!(eval(&lua, everyday).unwrap(), "oops");
+    }
+}
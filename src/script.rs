@@ -0,0 +1,657 @@
+//! Running a Lua command.
+//! Commands run as coroutines, so they can stop to ask the user something (with `Mega.ask`),
+//! wait for Mega to finish talking or just sleep, and pick up where they left off afterwards.
+//! While a command waits, and once it's done but still has callbacks waiting to be run
+//! (like timers from `Mega.every`), it sits in the background so Mega can get on with listening.
+
+use crate::audio::speech_synthesis::Voice;
+use crate::parsing::slots::SlotValue;
+use crate::parsing::{self, numbers, spelling};
+use crate::sandbox::{self, Budget, Sandbox};
+use crate::store::{Store, Values};
+
+use chrono::{Local, TimeZone};
+use rlua::{
+    Context, Error as LuaError, Function, Lua, MultiValue, RegistryKey, Table, ThreadStatus,
+    Value,
+};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// The longest `Mega.sleep` can go for, in seconds. A day ought to be enough for anyone.
+const MAX_SLEEP_SECONDS: f64 = 60.0 * 60.0 * 24.0;
+
+/// How deep tables in `Mega.store` can go. Mostly to catch tables that contain themselves.
+const MAX_STORE_DEPTH: usize = 32;
+
+/// The parts of the API that have to be written in Lua, because they yield.
+/// Rust functions can't yield, but they can be yielded from.
+/// It gets the Rust functions that actually say things and tell the time,
+/// and hands back the functions Rust calls to run the callbacks.
+const PRELUDE: &str = r#"
+local say, clock = ...
+-- Functions to call once something's been said, by its id
+local on_spoken = {}
+-- Timers from `Mega.after` and `Mega.every`, by id
+local timers = {}
+local next_timer = 1
+
+function Mega.speak(message, options)
+    if type(options) == "function" then
+        options = { on_done = options }
+    end
+    options = options or {}
+    local id = say(message)
+    if options.on_done then
+        on_spoken[id] = options.on_done
+    end
+    if options.wait then
+        coroutine.yield("speech", id)
+    end
+end
+
+function Mega.speak_and_wait(message)
+    Mega.speak(message, { wait = true })
+end
+
+function Mega.ask(prompt)
+    return coroutine.yield("ask", prompt)
+end
+
+function Mega.confirm(prompt)
+    return coroutine.yield("confirm", prompt)
+end
+
+function Mega.sleep(seconds)
+    coroutine.yield("sleep", seconds)
+end
+
+local function schedule(seconds, interval, callback)
+    if type(callback) ~= "function" then
+        error("the callback has to be a function", 3)
+    end
+    local id = next_timer
+    next_timer = next_timer + 1
+    timers[id] = { due = clock() + seconds, interval = interval, callback = callback }
+    return id
+end
+
+function Mega.after(seconds, callback)
+    return schedule(seconds, nil, callback)
+end
+
+function Mega.every(seconds, callback)
+    if not (seconds > 0) then
+        error("timers have to wait more than 0 seconds between runs", 2)
+    end
+    return schedule(seconds, seconds, callback)
+end
+
+function Mega.cancel(timer)
+    timers[timer] = nil
+end
+
+local hooks = {}
+
+function hooks.spoken(id)
+    local callback = on_spoken[id]
+    if callback then
+        on_spoken[id] = nil
+        callback()
+    end
+end
+
+function hooks.tick()
+    local now = clock()
+    -- Find them all first; the callbacks might add more
+    local due = {}
+    for id, timer in pairs(timers) do
+        if timer.due <= now then
+            table.insert(due, id)
+        end
+    end
+    table.sort(due, function(a, b) return timers[a].due < timers[b].due end)
+    for _, id in ipairs(due) do
+        local timer = timers[id]
+        -- An earlier callback might have cancelled it
+        if timer then
+            if timer.interval then
+                -- Don't try to catch up on runs we missed
+                timer.due = math.max(timer.due + timer.interval, now)
+            else
+                timers[id] = nil
+            end
+            timer.callback()
+        end
+    end
+end
+
+function hooks.pending()
+    return next(on_spoken) ~= nil or next(timers) ~= nil
+end
+
+return hooks
+"#;
+
+/// What a command stopped to wait for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// `Mega.ask`: say this, and hand back whatever they say
+    Ask(String),
+    /// `Mega.confirm`: say this, and hand back whether they said yes
+    Confirm(String),
+}
+
+impl Request {
+    pub fn prompt(&self) -> &str {
+        match self {
+            Request::Ask(prompt) | Request::Confirm(prompt) => prompt,
+        }
+    }
+}
+
+/// What a command gets back when it picks up again
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// Nothing; it's just starting
+    Start,
+    /// Nothing; whatever it was waiting for is done
+    Continue,
+    /// What they said to `Mega.ask`, as guesses at each word. None if they didn't say anything.
+    Answer(Option<Vec<Vec<String>>>),
+    /// Whether they said yes to `Mega.confirm`. None if they didn't say anything or it wasn't clear.
+    Confirmed(Option<bool>),
+}
+
+/// A Lua command, part way through running.
+pub struct Script {
+    pub path: PathBuf,
+    lua: Lua,
+    /// How much it's done since it last stopped to wait
+    budget: Budget,
+    /// The coroutine running the command. It's kept in the Lua registry,
+    /// since Lua values can't outlive `Lua::context`.
+    thread: RegistryKey,
+    /// The functions the prelude hands back, for running callbacks
+    hooks: RegistryKey,
+    /// What it's waiting for before it can carry on, if anything
+    waiting: Option<Wait>,
+    /// Whether the command's got to the end (though it might still have callbacks to run)
+    finished: bool,
+}
+
+/// Something a command is waiting for, that doesn't need the user
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Wait {
+    /// Mega to finish saying this, with `Mega.speak_and_wait`
+    Speech(u64),
+    /// This time to come around, with `Mega.sleep`
+    Until(Instant),
+}
+
+impl Script {
+    /// Set up the Mega api and load the command, ready to start running.
+    pub fn new(
+        voice: Voice,
+        path: PathBuf,
+        args: Vec<Vec<String>>,
+        slots: BTreeMap<String, SlotValue>,
+        confidence: f64,
+        store: Store,
+        sandbox: Sandbox,
+    ) -> Result<Self, String> {
+        let file = fs::read(&path).map_err(|err| format!("{:?}: {}", path, err))?;
+
+        // Initialize Lua, with only what the command's allowed
+        let (lua, budget) = sandbox.lua()?;
+        let started = Instant::now();
+        let (thread, hooks) = lua
+            .context(|ctx| {
+                // Initialize the Mega api!
+                let mega_api = ctx.create_table()?;
+                // Mega.parse_number
+                let parse_number = ctx.create_function(|_, (words,): (Value,)| {
+                    let words = match lua_words(words)? {
+                        Some(words) => words,
+                        None => return Ok((Value::Nil, false)),
+                    };
+                    Ok(match numbers::parse_number(&words) {
+                        Some(number) => (lua_number(number.value), number.ordinal),
+                        None => (Value::Nil, false),
+                    })
+                })?;
+                mega_api.set("parse_number", parse_number)?;
+                // Mega.parse_time
+                let parse_time = ctx.create_function(|_, (words, now): (Value, Option<i64>)| {
+                    let words = match lua_words(words)? {
+                        Some(words) => words,
+                        None => return Ok(None),
+                    };
+                    // Lua does times as seconds since 1970, like `os.time()`
+                    let now = match now {
+                        Some(now) => match Local.timestamp_opt(now, 0).single() {
+                            Some(now) => now.naive_local(),
+                            None => return Ok(None),
+                        },
+                        None => Local::now().naive_local(),
+                    };
+                    Ok(parsing::time::parse_time(&words, now)
+                        .and_then(|when| Local.from_local_datetime(&when).earliest())
+                        .map(|when| when.timestamp()))
+                })?;
+                mega_api.set("parse_time", parse_time)?;
+                // Mega.parse_duration
+                let parse_duration = ctx.create_function(|_, (words,): (Value,)| {
+                    Ok(match lua_words(words)? {
+                        Some(words) => parsing::time::parse_duration(&words).map(|duration| {
+                            lua_number(duration.num_milliseconds() as f64 / 1000.0)
+                        }),
+                        None => None,
+                    })
+                })?;
+                mega_api.set("parse_duration", parse_duration)?;
+                // Mega.decode_spelling
+                let decode_spelling = ctx.create_function(|_, (words,): (Value,)| {
+                    Ok(match lua_columns(words)? {
+                        Some(columns) => spelling::decode_spelling(&columns),
+                        None => None,
+                    })
+                })?;
+                mega_api.set("decode_spelling", decode_spelling)?;
+                // Mega.confidence
+                mega_api.set("confidence", confidence)?;
+                // Mega.store
+                mega_api.set("store", lua_store(ctx, store)?)?;
+
+                // Add the arguments to `Mega.arguments` and `Mega.raw_arguments`
+                let (mega_arguments, mega_raw_arguments) = lua_arguments(ctx, &args)?;
+                mega_api.set("arguments", mega_arguments)?;
+                mega_api.set("raw_arguments", mega_raw_arguments)?;
+                // And any numbers in them to `Mega.numbers`
+                let mega_numbers = ctx.create_table()?;
+                for (idx, number) in numbers::find_numbers(&args).into_iter().enumerate() {
+                    mega_numbers.set(idx + 1, lua_number(number.value))?;
+                }
+                mega_api.set("numbers", mega_numbers)?;
+                // Mega.slots
+                let mega_slots = ctx.create_table()?;
+                for (name, value) in slots {
+                    match value {
+                        SlotValue::Number(number) => mega_slots.set(name, lua_number(number))?,
+                        SlotValue::Text(text) => mega_slots.set(name, text)?,
+                    }
+                }
+                mega_api.set("slots", mega_slots)?;
+
+                // Give Lua access to the Mega api table
+                ctx.globals().set("Mega", mega_api)?;
+                // Mega.speak and friends get this to actually say things with
+                let say = ctx.create_function(move |_, (msg,): (String,)| {
+                    voice.say(msg).map_err(LuaError::RuntimeError)
+                })?;
+                // And this to tell the time with, in seconds since the command started
+                let clock =
+                    ctx.create_function(move |_, ()| Ok(started.elapsed().as_secs_f64()))?;
+                let hooks = ctx
+                    .load(PRELUDE)
+                    .set_name("Mega api")?
+                    .call::<_, Table>((say, clock))?;
+
+                // Seed the random generator
+                ctx.load("math.randomseed(os.time())")
+                    .set_name("random seeder")?
+                    .exec()?;
+
+                // Load the command file, ready to run
+                let command = ctx
+                    .load(&file)
+                    .set_name(&*path.to_string_lossy())?
+                    .into_function()?;
+                let thread = ctx.create_thread(command)?;
+                Ok((
+                    ctx.create_registry_value(thread)?,
+                    ctx.create_registry_value(hooks)?,
+                ))
+            })
+            .map_err(|err: LuaError| err.to_string())?;
+
+        Ok(Self {
+            path,
+            lua,
+            budget,
+            thread,
+            hooks,
+            waiting: None,
+            finished: false,
+        })
+    }
+
+    /// Let the command know what's happened since last time (like Mega finishing saying something),
+    /// and carry on running it if it's not waiting for anything.
+    /// `reply` is what to hand it, if it's been waiting on the user.
+    pub fn pump(
+        &mut self,
+        spoken: &[u64],
+        reply: Option<Reply>,
+    ) -> Result<Option<Request>, String> {
+        self.catch_up(spoken)?;
+        let reply = match reply {
+            Some(reply) => reply,
+            None if self.is_ready() => Reply::Continue,
+            None => return Ok(None),
+        };
+        self.resume(reply)
+    }
+
+    /// Let the command know what's happened since last time, and run any callbacks that are due,
+    /// but don't carry on running the command itself.
+    pub fn catch_up(&mut self, spoken: &[u64]) -> Result<(), String> {
+        for &id in spoken {
+            self.spoken(id)?;
+        }
+        self.tick()
+    }
+
+    /// Let the command know Mega's finished saying something, and run the callback for it
+    /// if there is one.
+    pub fn spoken(&mut self, id: u64) -> Result<(), String> {
+        if self.waiting == Some(Wait::Speech(id)) {
+            self.waiting = None;
+        }
+        self.call_hook("spoken", id)
+    }
+
+    /// Run any timers that are due, and see if it's done sleeping.
+    fn tick(&mut self) -> Result<(), String> {
+        if let Some(Wait::Until(time)) = self.waiting {
+            if Instant::now() >= time {
+                self.waiting = None;
+            }
+        }
+        self.call_hook("tick", ())
+    }
+
+    /// Whether the command's stopped to wait for something that isn't here yet
+    pub fn is_waiting(&self) -> bool {
+        self.waiting.is_some()
+    }
+
+    /// Whether the command's done waiting, and has more to run
+    pub fn is_ready(&self) -> bool {
+        !self.finished && !self.is_waiting()
+    }
+
+    /// Whether the command's got to the end
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whether there are callbacks still to run, like timers or things to do once something's been said
+    pub fn has_callbacks(&self) -> Result<bool, String> {
+        let hooks = &self.hooks;
+        self.lua
+            .context(|ctx| {
+                let pending = ctx.registry_value::<Table>(hooks)?.get::<_, Function>("pending")?;
+                pending.call::<_, bool>(())
+            })
+            .map_err(|err: LuaError| err.to_string())
+    }
+
+    fn call_hook<A>(&self, name: &str, args: A) -> Result<(), String>
+    where
+        A: for<'lua> rlua::ToLuaMulti<'lua>,
+    {
+        let hooks = &self.hooks;
+        self.budget.reset();
+        self.lua
+            .context(|ctx| {
+                let hook = ctx.registry_value::<Table>(hooks)?.get::<_, Function>(name)?;
+                hook.call::<_, ()>(args)
+            })
+            .map_err(|err: LuaError| err.to_string())?;
+        // In case it caught running out and carried on anyway
+        self.budget.check()
+    }
+
+    /// Keep running the command until it finishes, or stops to wait for something.
+    /// Returns what the user needs to be asked, if anything.
+    /// Waiting for Mega to finish talking or sleeping are handled here; see `spoken` and `tick`.
+    pub fn resume(&mut self, reply: Reply) -> Result<Option<Request>, String> {
+        let thread_key = &self.thread;
+        self.budget.reset();
+        let (finished, waiting, request) = self
+            .lua
+            .context(|ctx| {
+                let thread = ctx.registry_value::<rlua::Thread>(thread_key)?;
+                let resumed: Result<MultiValue, LuaError> = match reply {
+                    Reply::Start | Reply::Continue => thread.resume(()),
+                    Reply::Answer(Some(columns)) => thread.resume(lua_arguments(ctx, &columns)?),
+                    Reply::Answer(None) => thread.resume((Value::Nil, Value::Nil)),
+                    Reply::Confirmed(confirmed) => thread.resume(confirmed),
+                };
+                let yielded = resumed.map_err(|err| sandbox::traceback(ctx, &thread, err))?;
+                if thread.status() != ThreadStatus::Resumable {
+                    // All done
+                    return Ok((true, None, None));
+                }
+
+                let (kind, what) = ctx.unpack_multi::<(Option<String>, Value)>(yielded)?;
+                match (kind.as_deref(), what) {
+                    (Some("speech"), Value::Integer(id)) => {
+                        Ok((false, Some(Wait::Speech(id as u64)), None))
+                    }
+                    (Some("sleep"), seconds) => {
+                        let seconds = ctx.unpack::<Option<f64>>(seconds)?.unwrap_or(0.0);
+                        // Clamp it, so `Duration` doesn't panic on silly numbers (NaN included)
+                        let seconds = if seconds.is_nan() {
+                            0.0
+                        } else {
+                            seconds.clamp(0.0, MAX_SLEEP_SECONDS)
+                        };
+                        let until = Instant::now() + Duration::from_secs_f64(seconds);
+                        Ok((false, Some(Wait::Until(until)), None))
+                    }
+                    (Some("ask"), prompt) => {
+                        let prompt = ctx.unpack::<Option<String>>(prompt)?.unwrap_or_default();
+                        Ok((false, None, Some(Request::Ask(prompt))))
+                    }
+                    (Some("confirm"), prompt) => {
+                        let prompt = ctx.unpack::<Option<String>>(prompt)?.unwrap_or_default();
+                        Ok((false, None, Some(Request::Confirm(prompt))))
+                    }
+                    _ => Err(LuaError::RuntimeError(
+                        "the command yielded something that wasn't from Mega's api".to_string(),
+                    )),
+                }
+            })
+            .map_err(|err: LuaError| err.to_string())?;
+        self.budget.check()?;
+        self.finished = finished;
+        self.waiting = waiting;
+        Ok(request)
+    }
+}
+
+/// Makes the `Mega.store` table
+fn lua_store(ctx: Context, store: Store) -> Result<Table, LuaError> {
+    let mega_store = ctx.create_table()?;
+    let get_store = store.clone();
+    let get = ctx.create_function(move |ctx, (key,): (String,)| {
+        match get_store.get(&key).map_err(LuaError::RuntimeError)? {
+            Some(value) => toml_to_lua(ctx, value),
+            None => Ok(Value::Nil),
+        }
+    })?;
+    mega_store.set("get", get)?;
+    let set_store = store.clone();
+    let set = ctx.create_function(move |_, (key, value): (String, Value)| {
+        match lua_to_toml(value, 0)? {
+            Some(value) => set_store.set(&key, value),
+            // Storing nil is the same as deleting it, like with tables
+            None => set_store.delete(&key).map(|_| ()),
+        }
+        .map_err(LuaError::RuntimeError)
+    })?;
+    mega_store.set("set", set)?;
+    let delete_store = store.clone();
+    let delete = ctx.create_function(move |_, (key,): (String,)| {
+        delete_store.delete(&key).map_err(LuaError::RuntimeError)
+    })?;
+    mega_store.set("delete", delete)?;
+    let keys = ctx.create_function(move |_, ()| store.keys().map_err(LuaError::RuntimeError))?;
+    mega_store.set("keys", keys)?;
+    Ok(mega_store)
+}
+
+/// Turns something from Lua into something that can go in the store. None if it's nil.
+fn lua_to_toml(value: Value, depth: usize) -> Result<Option<toml::Value>, LuaError> {
+    if depth > MAX_STORE_DEPTH {
+        return Err(LuaError::RuntimeError(
+            "that table is too deep to store (does it contain itself?)".to_string(),
+        ));
+    }
+    Ok(Some(match value {
+        Value::Nil => return Ok(None),
+        Value::Boolean(boolean) => toml::Value::Boolean(boolean),
+        Value::Integer(integer) => toml::Value::Integer(integer),
+        Value::Number(number) => toml::Value::Float(number),
+        Value::String(string) => toml::Value::String(string.to_str()?.to_string()),
+        Value::Table(table) => {
+            let len = table.raw_len() as usize;
+            let pairs = table
+                .clone()
+                .pairs::<Value, Value>()
+                .collect::<Result<Vec<_>, _>>()?;
+            if len > 0 && pairs.len() == len {
+                // A list
+                let mut items = Vec::with_capacity(len);
+                for idx in 1..=len {
+                    let item = table.raw_get::<_, Value>(idx)?;
+                    items.extend(lua_to_toml(item, depth + 1)?);
+                }
+                toml::Value::Array(items)
+            } else {
+                let mut values = Values::new();
+                for (key, value) in pairs {
+                    let key = match key {
+                        Value::String(key) => key.to_str()?.to_string(),
+                        Value::Integer(key) => key.to_string(),
+                        _ => {
+                            return Err(LuaError::RuntimeError(
+                                "only strings and whole numbers can be keys in stored tables"
+                                    .to_string(),
+                            ))
+                        }
+                    };
+                    if let Some(value) = lua_to_toml(value, depth + 1)? {
+                        values.insert(key, value);
+                    }
+                }
+                toml::Value::Table(values)
+            }
+        }
+        other => {
+            return Err(LuaError::RuntimeError(format!(
+                "can't store a {}",
+                other.type_name()
+            )))
+        }
+    }))
+}
+
+/// Turns something from the store back into something for Lua
+fn toml_to_lua(ctx: Context, value: toml::Value) -> Result<Value, LuaError> {
+    Ok(match value {
+        toml::Value::Boolean(boolean) => Value::Boolean(boolean),
+        toml::Value::Integer(integer) => Value::Integer(integer),
+        toml::Value::Float(number) => Value::Number(number),
+        toml::Value::String(string) => Value::String(ctx.create_string(&string)?),
+        toml::Value::Datetime(datetime) => Value::String(ctx.create_string(&datetime.to_string())?),
+        toml::Value::Array(items) => {
+            let table = ctx.create_table()?;
+            for (idx, item) in items.into_iter().enumerate() {
+                table.set(idx + 1, toml_to_lua(ctx, item)?)?;
+            }
+            Value::Table(table)
+        }
+        toml::Value::Table(values) => {
+            let table = ctx.create_table()?;
+            for (key, value) in values {
+                table.set(key, toml_to_lua(ctx, value)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+/// Makes the tables for `Mega.arguments` and `Mega.raw_arguments` out of the guesses at each word
+fn lua_arguments<'lua>(
+    ctx: Context<'lua>,
+    args: &[Vec<String>],
+) -> Result<(Table<'lua>, Table<'lua>), LuaError> {
+    let mega_arguments = ctx.create_table()?;
+    let mega_raw_arguments = ctx.create_table()?;
+    for (arg_idx, possible_args) in args.iter().enumerate() {
+        let mega_possibilities = ctx.create_table()?;
+        for (possible_idx, possibility) in possible_args.iter().enumerate() {
+            if possible_idx == 0 {
+                // add 1 to everything, because lua counts at 1...
+                mega_arguments.set(arg_idx + 1, possibility.clone())?;
+            }
+            mega_possibilities.set(possible_idx + 1, possibility.clone())?;
+        }
+        mega_raw_arguments.set(arg_idx + 1, mega_possibilities)?;
+    }
+    Ok((mega_arguments, mega_raw_arguments))
+}
+
+/// Words passed in from Lua, either as one string or a table of them.
+/// None if it's something else entirely.
+fn lua_words(words: Value) -> Result<Option<Vec<String>>, LuaError> {
+    Ok(match words {
+        Value::String(words) => Some(
+            words
+                .to_str()?
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+        ),
+        Value::Table(words) => Some(words.sequence_values::<String>().collect::<Result<_, _>>()?),
+        _ => None,
+    })
+}
+
+/// Like `lua_words`, but it can also take a table of tables of guesses at each word,
+/// like `Mega.raw_arguments`.
+fn lua_columns(words: Value) -> Result<Option<Vec<Vec<String>>>, LuaError> {
+    if let Value::Table(table) = &words {
+        let columns = table.clone().sequence_values::<Value>().collect::<Result<Vec<_>, _>>()?;
+        let tables = columns
+            .iter()
+            .filter_map(|column| match column {
+                Value::Table(column) => Some(column.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !tables.is_empty() && tables.len() == columns.len() {
+            let columns = tables
+                .into_iter()
+                .map(|column| column.sequence_values::<String>().collect())
+                .collect::<Result<_, _>>()?;
+            return Ok(Some(columns));
+        }
+    }
+    Ok(lua_words(words)?.map(|words| words.into_iter().map(|word| vec![word]).collect()))
+}
+
+/// Whole numbers go to Lua as integers, so `string.format("%d")` and friends work on them.
+fn lua_number<'lua>(value: f64) -> Value<'lua> {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Value::Integer(value as i64)
+    } else {
+        Value::Number(value)
+    }
+}
@@ -0,0 +1,167 @@
+//! Somewhere for commands to keep things between runs, like a shopping list or a high score.
+//! Each command gets its own file in the store folder, named after where the command is,
+//! so `commands/game/score.lua` keeps its things in `store/game/score.toml`.
+
+use toml::Value;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Everything in a store, by key
+pub type Values = toml::value::Table;
+
+/// One command's store.
+/// It's read fresh every time, in case another run of the same command changed it in the meantime.
+#[derive(Debug, Clone)]
+pub struct Store {
+    path: PathBuf,
+    /// How big the file is allowed to get
+    max_bytes: usize,
+}
+
+impl Store {
+    /// The store for the command at `command`, which is relative to the commands folder.
+    pub fn for_command<P: AsRef<Path>>(folder: P, command: &Path, max_bytes: usize) -> Self {
+        // Only the names, so nothing ends up outside the store folder
+        let names = command.components().filter_map(|part| match part {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        });
+        let mut path = folder.as_ref().to_path_buf();
+        path.extend(names);
+        Self {
+            path: path.with_extension("toml"),
+            max_bytes,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Value>, String> {
+        Ok(self.load()?.remove(key))
+    }
+
+    pub fn set(&self, key: &str, value: Value) -> Result<(), String> {
+        let mut values = self.load()?;
+        values.insert(key.to_string(), value);
+        self.save(values)
+    }
+
+    /// Returns whether there was anything there to delete
+    pub fn delete(&self, key: &str) -> Result<bool, String> {
+        let mut values = self.load()?;
+        if values.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.save(values)?;
+        Ok(true)
+    }
+
+    pub fn keys(&self) -> Result<Vec<String>, String> {
+        Ok(self.load()?.into_iter().map(|(key, _)| key).collect())
+    }
+
+    fn load(&self) -> Result<Values, String> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => toml::from_str(&text).map_err(|err| format!("{:?}: {}", self.path, err)),
+            // Nothing's been stored yet
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Values::new()),
+            Err(err) => Err(format!("{:?}: {}", self.path, err)),
+        }
+    }
+
+    fn save(&self, values: Values) -> Result<(), String> {
+        // As a `Value`, it knows to put the tables last like toml wants
+        let text = toml::to_string(&Value::Table(values))
+            .map_err(|err| format!("can't store that: {}", err))?;
+        if text.len() > self.max_bytes {
+            return Err(format!(
+                "that would make the store {} bytes, and it can only be {}",
+                text.len(),
+                self.max_bytes
+            ));
+        }
+
+        // Write it next door first and then swap it in, so it's never left half-written
+        let temp = self.path.with_extension("toml.tmp");
+        let write = || -> io::Result<()> {
+            if let Some(folder) = self.path.parent() {
+                fs::create_dir_all(folder)?;
+            }
+            let mut file = fs::File::create(&temp)?;
+            file.write_all(text.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temp, &self.path)
+        };
+        write().map_err(|err| format!("{:?}: {}", self.path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty store folder all to this test
+    fn folder(name: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("mega-store-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&folder);
+        folder
+    }
+
+    #[test]
+    fn keeps_things_between_runs() {
+        let folder = folder("round-trip");
+        let store = Store::for_command(&folder, Path::new("game/score.lua"), 1000);
+        assert_eq!(store.get("high").unwrap(), None);
+
+        let mut list = Values::new();
+        list.insert("milk".to_string(), Value::Integer(2));
+        store.set("high", Value::Integer(9001)).unwrap();
+        store.set("shopping", Value::Table(list.clone())).unwrap();
+        store.set("name", Value::String("bob".to_string())).unwrap();
+        assert!(folder.join("game").join("score.toml").is_file());
+
+        // A new store for the same command sees the same things
+        let again = Store::for_command(&folder, Path::new("game/score.lua"), 1000);
+        assert_eq!(again.get("high").unwrap(), Some(Value::Integer(9001)));
+        assert_eq!(again.get("shopping").unwrap(), Some(Value::Table(list)));
+        assert_eq!(again.keys().unwrap(), vec!["high", "name", "shopping"]);
+
+        assert!(again.delete("name").unwrap());
+        assert!(!again.delete("name").unwrap());
+        assert_eq!(store.get("name").unwrap(), None);
+    }
+
+    #[test]
+    fn stays_in_its_folder() {
+        let folder = folder("confined");
+        let store = Store::for_command(&folder, Path::new("../../etc/time.lua"), 1000);
+        store.set("x", Value::Boolean(true)).unwrap();
+        assert!(folder.join("etc").join("time.toml").is_file());
+    }
+
+    #[test]
+    fn cant_grow_past_the_limit() {
+        let folder = folder("limit");
+        let store = Store::for_command(&folder, Path::new("list.lua"), 32);
+        store.set("a", Value::String("short".to_string())).unwrap();
+        let long = Value::String("x".repeat(40));
+        assert!(store.set("b", long).is_err());
+        // What was there before is still there
+        assert_eq!(store.keys().unwrap(), vec!["a"]);
+        assert_eq!(
+            store.get("a").unwrap(),
+            Some(Value::String("short".to_string()))
+        );
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        let folder = folder("broken");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("list.toml"), "this isn't = = toml").unwrap();
+        let store = Store::for_command(&folder, Path::new("list.lua"), 1000);
+        assert!(store.get("a").is_err());
+        assert!(store.set("a", Value::Integer(1)).is_err());
+    }
+}